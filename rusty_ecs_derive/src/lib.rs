@@ -0,0 +1,250 @@
+//! Derive macros for `rusty_ecs`, re-exported from there.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr, Path, Result};
+
+/// Implements `Bundle` for a struct, with one component per field. Fields
+/// marked `#[bundle]` are bundles themselves, whose components are added in
+/// their place.
+#[proc_macro_derive(Bundle, attributes(bundle))]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    bundle(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Implements `ComponentConfig`, which every world applies the first time it
+/// sees the type.
+///
+/// ```ignore
+/// #[derive(Debug, Component)]
+/// #[component(storage = "sparse_set", on_add = on_add_target)]
+/// struct Target(Entity);
+/// ```
+///
+/// `storage` is `"table"` (the default) or `"sparse_set"`, and `on_add`,
+/// `on_insert` and `on_remove` take paths to `ComponentHook` functions. The
+/// configuration is registered before `main` runs, so the attributes aren't
+/// supported on generic types or on targets without static initializers.
+#[proc_macro_derive(Component, attributes(component))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    component(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn bundle(input: DeriveInput) -> Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "`Bundle` can only be derived for structs",
+        ));
+    };
+
+    let mut type_info = Vec::new();
+    let mut put = Vec::new();
+    let mut take = Vec::new();
+    let mut members = Vec::new();
+    let mut bindings = Vec::new();
+
+    for (index, field) in data.fields.iter().enumerate() {
+        let ty = &field.ty;
+        let binding = format_ident!("__field_{}", index);
+
+        let nested = field
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("bundle"));
+
+        if nested {
+            type_info.push(quote! {
+                infos.extend(<#ty as ::rusty_ecs::Bundle>::type_info());
+            });
+            put.push(quote! {
+                <#ty as ::rusty_ecs::Bundle>::put(#binding, &mut *f);
+            });
+            take.push(quote! {
+                <#ty as ::rusty_ecs::Bundle>::take(&mut *f)
+            });
+        } else {
+            type_info.push(quote! {
+                infos.push(::rusty_ecs::TypeInfo::of::<#ty>());
+            });
+            put.push(quote! {
+                let mut #binding = ::core::mem::ManuallyDrop::new(#binding);
+                f(
+                    &mut *#binding as *mut #ty as *mut u8,
+                    ::rusty_ecs::TypeInfo::of::<#ty>(),
+                );
+            });
+            take.push(quote! {
+                ::core::ptr::read(f(::rusty_ecs::TypeInfo::of::<#ty>()) as *const #ty)
+            });
+        }
+
+        members.push(match &field.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = syn::Index::from(index);
+                quote!(#index)
+            }
+        });
+        bindings.push(binding);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let destructure = match data.fields {
+        Fields::Unit => quote!(let _ = self;),
+        _ => quote!(let Self { #(#members: #bindings),* } = self;),
+    };
+
+    Ok(quote! {
+        unsafe impl #impl_generics ::rusty_ecs::Bundle for #name #ty_generics #where_clause {
+            fn type_info() -> ::std::vec::Vec<::rusty_ecs::TypeInfo> {
+                let mut infos = ::std::vec::Vec::new();
+                #(#type_info)*
+                infos
+            }
+
+            unsafe fn put(self, f: &mut dyn FnMut(*mut u8, ::rusty_ecs::TypeInfo)) {
+                #destructure
+                #(#put)*
+            }
+
+            unsafe fn take(f: &mut dyn FnMut(::rusty_ecs::TypeInfo) -> *mut u8) -> Self {
+                Self { #(#members: #take),* }
+            }
+        }
+    })
+}
+
+fn component(input: DeriveInput) -> Result<TokenStream2> {
+    let mut storage = None;
+    let mut hooks = Vec::new();
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("component"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("storage") {
+                let value = meta.value()?.parse::<LitStr>()?;
+
+                storage = Some(match value.value().as_str() {
+                    "table" => quote!(::rusty_ecs::StorageType::Table),
+                    "sparse_set" => quote!(::rusty_ecs::StorageType::SparseSet),
+                    _ => {
+                        return Err(Error::new_spanned(
+                            value,
+                            "expected `\"table\"` or `\"sparse_set\"`",
+                        ))
+                    }
+                });
+
+                return Ok(());
+            }
+
+            for hook in ["on_add", "on_insert", "on_remove"] {
+                if meta.path.is_ident(hook) {
+                    let hook = format_ident!("{}", hook);
+                    let path = meta.value()?.parse::<Path>()?;
+
+                    hooks.push(quote!(hooks.#hook(#path);));
+
+                    return Ok(());
+                }
+            }
+
+            Err(meta.error("expected `storage`, `on_add`, `on_insert` or `on_remove`"))
+        })?;
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let configured = storage.is_some() || !hooks.is_empty();
+
+    if configured && !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "`#[component]` attributes aren't supported on generic types",
+        ));
+    }
+
+    let register = configured.then(|| register_config(name));
+
+    let storage =
+        storage.map(|storage| quote!(const STORAGE_TYPE: ::rusty_ecs::StorageType = #storage;));
+
+    let register_hooks = (!hooks.is_empty()).then(|| {
+        quote! {
+            fn register_hooks(hooks: &mut ::rusty_ecs::ComponentHooks) {
+                #(#hooks)*
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::rusty_ecs::ComponentConfig for #name #ty_generics #where_clause {
+            #storage
+            #register_hooks
+        }
+
+        #register
+    })
+}
+
+/// Registers the configuration of `name` on startup, through the section of
+/// functions the platform runs before `main`.
+fn register_config(name: &syn::Ident) -> TokenStream2 {
+    quote! {
+        #[cfg(not(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd",
+            target_os = "dragonfly",
+            target_os = "illumos",
+            target_vendor = "apple",
+            windows,
+        )))]
+        ::core::compile_error!(
+            "`#[component]` attributes need static initializers; implement `ComponentConfig` and call `World::init_component` instead"
+        );
+
+        const _: () = {
+            #[used]
+            #[cfg_attr(
+                any(
+                    target_os = "linux",
+                    target_os = "android",
+                    target_os = "freebsd",
+                    target_os = "netbsd",
+                    target_os = "openbsd",
+                    target_os = "dragonfly",
+                    target_os = "illumos",
+                ),
+                unsafe(link_section = ".init_array")
+            )]
+            #[cfg_attr(target_vendor = "apple", unsafe(link_section = "__DATA,__mod_init_func"))]
+            #[cfg_attr(windows, unsafe(link_section = ".CRT$XCU"))]
+            static REGISTER: extern "C" fn() = {
+                extern "C" fn register() {
+                    ::rusty_ecs::register_config::<#name>();
+                }
+
+                register
+            };
+        };
+    }
+}
@@ -0,0 +1,129 @@
+use std::any::TypeId;
+
+use rustc_hash::FxHashSet;
+
+/// The set of component and resource types something reads and writes.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Access {
+    reads: FxHashSet<TypeId>,
+    writes: FxHashSet<TypeId>,
+    resource_reads: FxHashSet<TypeId>,
+    resource_writes: FxHashSet<TypeId>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_read(&mut self, id: TypeId) {
+        self.reads.insert(id);
+    }
+
+    pub fn add_write(&mut self, id: TypeId) {
+        self.writes.insert(id);
+    }
+
+    pub fn has_read(&self, id: TypeId) -> bool {
+        self.reads.contains(&id)
+    }
+
+    pub fn has_write(&self, id: TypeId) -> bool {
+        self.writes.contains(&id)
+    }
+
+    pub fn reads(&self) -> impl Iterator<Item = &TypeId> {
+        self.reads.iter()
+    }
+
+    pub fn writes(&self) -> impl Iterator<Item = &TypeId> {
+        self.writes.iter()
+    }
+
+    pub fn add_resource_read(&mut self, id: TypeId) {
+        self.resource_reads.insert(id);
+    }
+
+    pub fn add_resource_write(&mut self, id: TypeId) {
+        self.resource_writes.insert(id);
+    }
+
+    pub fn has_resource_read(&self, id: TypeId) -> bool {
+        self.resource_reads.contains(&id)
+    }
+
+    pub fn has_resource_write(&self, id: TypeId) -> bool {
+        self.resource_writes.contains(&id)
+    }
+
+    /// Merges everything `other` accesses into this set.
+    pub fn extend(&mut self, other: &Access) {
+        self.reads.extend(other.reads.iter().copied());
+        self.writes.extend(other.writes.iter().copied());
+        self.resource_reads
+            .extend(other.resource_reads.iter().copied());
+        self.resource_writes
+            .extend(other.resource_writes.iter().copied());
+    }
+
+    /// Returns `true` if neither side writes anything the other side touches.
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        fn compatible(
+            reads: &FxHashSet<TypeId>,
+            writes: &FxHashSet<TypeId>,
+            other_reads: &FxHashSet<TypeId>,
+            other_writes: &FxHashSet<TypeId>,
+        ) -> bool {
+            writes.is_disjoint(other_reads)
+                && writes.is_disjoint(other_writes)
+                && other_writes.is_disjoint(reads)
+        }
+
+        compatible(&self.reads, &self.writes, &other.reads, &other.writes)
+            && compatible(
+                &self.resource_reads,
+                &self.resource_writes,
+                &other.resource_reads,
+                &other.resource_writes,
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compatible() {
+        let mut a = Access::new();
+        a.add_read(TypeId::of::<u32>());
+
+        let mut b = Access::new();
+        b.add_read(TypeId::of::<u32>());
+        b.add_write(TypeId::of::<u64>());
+
+        assert!(a.is_compatible(&b));
+
+        a.add_read(TypeId::of::<u64>());
+
+        assert!(!a.is_compatible(&b));
+        assert!(!b.is_compatible(&a));
+    }
+
+    #[test]
+    fn test_resources() {
+        let mut a = Access::new();
+        a.add_write(TypeId::of::<u32>());
+        a.add_resource_read(TypeId::of::<u64>());
+
+        let mut b = Access::new();
+        b.add_resource_read(TypeId::of::<u32>());
+        b.add_resource_read(TypeId::of::<u64>());
+
+        assert!(a.is_compatible(&b));
+
+        b.add_resource_write(TypeId::of::<u64>());
+
+        assert!(!a.is_compatible(&b));
+    }
+}
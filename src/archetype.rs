@@ -1,8 +1,12 @@
-use std::any::TypeId;
+use std::{any::TypeId, fmt};
 
 use rustc_hash::FxHashMap;
 
-use crate::component::Component;
+use crate::{
+    column::Column,
+    component::{Component, TypeInfo},
+    ComponentTicks, Entity, Tick,
+};
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct ArchetypeId(usize);
@@ -11,98 +15,233 @@ impl ArchetypeId {
     pub const INVALID: Self = ArchetypeId(usize::MAX);
 }
 
-#[derive(Debug)]
+/// Counts how many archetypes existed at some point, so that anything caching
+/// per-archetype data can tell which archetypes it hasn't seen yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ArchetypeGeneration(usize);
+
+impl ArchetypeGeneration {
+    pub const fn initial() -> Self {
+        ArchetypeGeneration(0)
+    }
+}
+
 pub struct Archetype {
     pub id: ArchetypeId,
     pub types: Box<[TypeId]>,
-    pub components: Vec<Vec<Box<dyn Component>>>, // Vec<SparseSet<Box<dyn Component>>>, //Vec<Vec<Box<dyn Component>>>,
-    add: FxHashMap<TypeId, ArchetypeId>,
+    pub(crate) components: Vec<Column>,
+    pub(crate) entities: Vec<Entity>,
+    edges: FxHashMap<TypeId, Edge>,
+    bundle_edges: FxHashMap<TypeId, Edge>,
     info: FxHashMap<TypeId, ComponentInfo>,
 }
 
 impl Archetype {
-    pub fn new(id: ArchetypeId, types: Box<[TypeId]>) -> Self {
+    /// Creates an archetype with a column for each type, which must already
+    /// be sorted by [`TypeId`].
+    pub fn new(id: ArchetypeId, infos: Vec<TypeInfo>) -> Self {
         Self {
             id,
-            components: Vec::from_iter(types.iter().map(|_| Vec::new())),
+            types: infos.iter().map(TypeInfo::id).collect(),
             info: FxHashMap::from_iter(
-                types
+                infos
                     .iter()
                     .enumerate()
-                    .map(|(pos, id)| (*id, ComponentInfo { column: pos })),
+                    .map(|(pos, info)| (info.id(), ComponentInfo { column: pos })),
             ),
-            types,
-            add: FxHashMap::default(),
+            components: infos.into_iter().map(Column::new).collect(),
+            entities: Vec::new(),
+            edges: FxHashMap::default(),
+            bundle_edges: FxHashMap::default(),
         }
     }
 
     pub fn has_component<C: Component>(&self) -> bool {
         let id = TypeId::of::<C>();
-        self.types.iter().any(|typ| *typ == id)
+        self.types.contains(&id)
     }
 
-    pub fn get_component<C: Component>(&self) -> Vec<&C> {
-        let index = self.get_component_index(&TypeId::of::<C>()).unwrap().column;
+    /// The columns of the archetype, in the same order as its types.
+    pub fn columns(&self) -> &[Column] {
+        &self.components
+    }
 
-        self.components[index]
-            .iter()
-            .map(|c| (**c).as_any().downcast_ref::<C>().unwrap())
-            .collect::<Vec<_>>()
+    pub fn column<C: Component>(&self) -> Option<&Column> {
+        let info = self.get_component_index(&TypeId::of::<C>())?;
+
+        Some(&self.components[info.column])
     }
 
-    pub fn get_component_mut<C: Component>(&mut self) -> Vec<&mut C> {
-        let index = self.get_component_index(&TypeId::of::<C>()).unwrap().column;
+    pub(crate) fn column_mut<C: Component>(&mut self) -> Option<&mut Column> {
+        let info = self.get_component_index(&TypeId::of::<C>())?;
 
-        self.components[index]
-            .iter_mut()
-            .map(|c| (**c).as_any_mut().downcast_mut::<C>().unwrap())
-            .collect::<Vec<_>>()
+        Some(&mut self.components[info.column])
     }
 
-    pub fn get_single<C: Component>(&self) -> &C {
-        let index = self.get_component_index(&TypeId::of::<C>()).unwrap().column;
+    pub fn get_component<C: Component>(&self) -> &[C] {
+        self.column::<C>().unwrap().as_slice()
+    }
 
-        self.components[index]
-            .first()
-            .unwrap()
-            .as_any()
-            .downcast_ref::<C>()
-            .unwrap()
+    pub(crate) fn get_component_mut<C: Component>(&mut self) -> &mut [C] {
+        self.column_mut::<C>().unwrap().as_mut_slice()
     }
 
-    pub fn get_single_mut<C: Component>(&mut self) -> &mut C {
-        let index = self.get_component_index(&TypeId::of::<C>()).unwrap().column;
+    pub fn get_single<C: Component>(&self) -> &C {
+        self.get_component::<C>().first().unwrap()
+    }
 
-        self.components[index]
-            .first_mut()
-            .unwrap()
-            .as_any_mut()
-            .downcast_mut::<C>()
-            .unwrap()
+    pub fn get_single_mut<C: Component>(&mut self) -> &mut C {
+        self.get_component_mut::<C>().first_mut().unwrap()
     }
 
     pub fn get_component_index(&self, id: &TypeId) -> Option<ComponentInfo> {
         self.info.get(id).copied()
     }
 
-    pub fn edges(&self) -> &FxHashMap<TypeId, ArchetypeId> {
-        &self.add
+    pub fn edges(&self) -> &FxHashMap<TypeId, Edge> {
+        &self.edges
+    }
+
+    pub(crate) fn edges_mut(&mut self) -> &mut FxHashMap<TypeId, Edge> {
+        &mut self.edges
     }
 
-    pub fn edges_mut(&mut self) -> &mut FxHashMap<TypeId, ArchetypeId> {
-        &mut self.add
+    /// The archetypes reached by inserting or removing a whole bundle, keyed
+    /// by the bundle's type. Unlike [`Archetype::edges`], an `add` edge here
+    /// has no matching `remove` edge back, since the entity may have had some
+    /// of the bundle's components already.
+    pub fn bundle_edges(&self) -> &FxHashMap<TypeId, Edge> {
+        &self.bundle_edges
+    }
+
+    pub(crate) fn bundle_edges_mut(&mut self) -> &mut FxHashMap<TypeId, Edge> {
+        &mut self.bundle_edges
     }
 
     pub fn id(&self) -> ArchetypeId {
         self.id
     }
 
+    /// The entity stored in each row.
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
     pub(crate) fn row(&self) -> usize {
-        self.components[0].len() - 1
+        self.entities.len() - 1
+    }
+
+    /// Moves the value behind `ptr` into the column for `id`.
+    ///
+    /// # Safety
+    /// The archetype must have a column for `id` and `ptr` must point to a
+    /// valid value of that type, which is moved into the column.
+    pub(crate) unsafe fn put(&mut self, id: TypeId, ptr: *const u8, ticks: ComponentTicks) {
+        let info = self.get_component_index(&id).unwrap();
+
+        self.components[info.column].push(ptr, ticks);
+    }
+
+    /// Creates a copy of the archetype with the same id, rows and edges,
+    /// making each column with `f`.
+    pub(crate) fn clone_with(&self, f: impl FnMut(&Column) -> Column) -> Self {
+        Self {
+            id: self.id,
+            types: self.types.clone(),
+            components: self.components.iter().map(f).collect(),
+            entities: self.entities.clone(),
+            edges: self.edges.clone(),
+            bundle_edges: self.bundle_edges.clone(),
+            info: self.info.clone(),
+        }
+    }
+
+    /// Drops the value in `row` of the column for `id` and moves the value
+    /// behind `ptr` into its place.
+    ///
+    /// # Safety
+    /// As for [`Archetype::put`], and `row` must be in bounds.
+    pub(crate) unsafe fn replace(&mut self, id: TypeId, row: usize, ptr: *const u8, changed: Tick) {
+        let info = self.get_component_index(&id).unwrap();
+
+        self.components[info.column].replace(row, ptr, changed);
+    }
+
+    /// Returns a pointer to the value in `row` of the column for `id`.
+    ///
+    /// # Safety
+    /// The archetype must have a column for `id`, and `row` must be in bounds.
+    pub(crate) unsafe fn get_ptr(&self, id: TypeId, row: usize) -> *mut u8 {
+        let info = self.get_component_index(&id).unwrap();
+
+        self.components[info.column].get_unchecked(row)
+    }
+
+    /// Drops a row, returning the entity that was moved into its place.
+    pub(crate) fn swap_remove(&mut self, row: usize) -> Option<Entity> {
+        for column in self.components.iter_mut() {
+            column.swap_remove(row);
+        }
+
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+
+    /// Moves a row into `target`, copying each component `target` has a column
+    /// for and handing the rest to `f`, which must read or drop them. Returns
+    /// the entity that was moved into the vacated row.
+    ///
+    /// Columns in `target` that this archetype doesn't have are left for the
+    /// caller to fill.
+    ///
+    /// # Safety
+    /// `row` must be in bounds.
+    pub(crate) unsafe fn move_row(
+        &mut self,
+        row: usize,
+        target: &mut Archetype,
+        mut f: impl FnMut(&TypeInfo, *mut u8),
+    ) -> Option<Entity> {
+        for column in self.components.iter_mut() {
+            let ptr = column.get_unchecked(row);
+
+            match target.get_component_index(&column.info().id()) {
+                Some(info) => {
+                    target.components[info.column].push(ptr, column.get_ticks(row).unwrap())
+                }
+                None => f(column.info(), ptr),
+            }
+
+            column.swap_remove_forget(row);
+        }
+
+        target.entities.push(self.entities.swap_remove(row));
+        self.entities.get(row).copied()
+    }
+}
+
+impl fmt::Debug for Archetype {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let types = self.components.iter().map(Column::info).collect::<Vec<_>>();
+
+        f.debug_struct("Archetype")
+            .field("id", &self.id)
+            .field("types", &types)
+            .field("entities", &self.entities)
+            .field("components", &self.components)
+            .finish()
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct Archetypes {
     pub(crate) archetypes: Vec<Archetype>,
     types: FxHashMap<Box<[TypeId]>, usize>,
@@ -110,21 +249,54 @@ pub struct Archetypes {
     count: usize,
 }
 
+impl fmt::Debug for Archetypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.archetypes).finish()
+    }
+}
+
 impl Archetypes {
+    /// Archetypes are never removed, so their count doubles as a generation.
+    pub fn generation(&self) -> ArchetypeGeneration {
+        ArchetypeGeneration(self.archetypes.len())
+    }
+
+    /// The archetypes created since `generation`.
+    pub fn since(&self, generation: ArchetypeGeneration) -> &[Archetype] {
+        &self.archetypes[generation.0..]
+    }
+
     pub fn has_archetype(&self, types: &[TypeId]) -> bool {
         self.types.contains_key(types)
     }
 
-    pub fn create_archetype(&mut self, types: &mut Box<[TypeId]>) -> ArchetypeId {
-        types.sort_unstable();
+    /// Looks up the archetype with exactly these types, creating it if it
+    /// doesn't exist yet.
+    pub fn get_or_create(&mut self, mut infos: Vec<TypeInfo>) -> ArchetypeId {
+        infos.sort_unstable_by_key(TypeInfo::id);
+
+        let types = infos.iter().map(TypeInfo::id).collect::<Box<[TypeId]>>();
+
+        match self.types.get(&types) {
+            Some(index) => self.archetypes[*index].id(),
+            None => self.create_archetype(infos),
+        }
+    }
+
+    pub fn create_archetype(&mut self, mut infos: Vec<TypeInfo>) -> ArchetypeId {
+        infos.sort_unstable_by_key(TypeInfo::id);
+
+        if let Some(pair) = infos.windows(2).find(|pair| pair[0].id() == pair[1].id()) {
+            panic!("component `{}` appears more than once", pair[0].type_name());
+        }
 
         let id = ArchetypeId(self.count);
         self.count += 1;
 
-        let archetype = Archetype::new(id, types.clone());
+        let archetype = Archetype::new(id, infos);
         let index = self.archetypes.len();
 
-        self.types.insert(types.clone(), index);
+        self.types.insert(archetype.types.clone(), index);
         self.ids.insert(id, index);
 
         self.archetypes.push(archetype);
@@ -132,6 +304,20 @@ impl Archetypes {
         id
     }
 
+    /// Creates a copy of every archetype, making each column with `f`.
+    pub(crate) fn clone_with(&self, mut f: impl FnMut(&Column) -> Column) -> Self {
+        Self {
+            archetypes: self
+                .archetypes
+                .iter()
+                .map(|archetype| archetype.clone_with(&mut f))
+                .collect(),
+            types: self.types.clone(),
+            ids: self.ids.clone(),
+            count: self.count,
+        }
+    }
+
     pub fn get_by_types_mut(&mut self, types: &[TypeId]) -> Option<&mut Archetype> {
         let index = *self.types.get(types)?;
 
@@ -149,6 +335,39 @@ impl Archetypes {
 
         &mut self.archetypes[index]
     }
+
+    /// Borrows two distinct archetypes at once.
+    pub fn get_pair_mut(
+        &mut self,
+        a: ArchetypeId,
+        b: ArchetypeId,
+    ) -> (&mut Archetype, &mut Archetype) {
+        let (a, b) = (self.ids[&a], self.ids[&b]);
+        assert_ne!(a, b);
+
+        if a < b {
+            let (left, right) = self.archetypes.split_at_mut(b);
+            (&mut left[a], &mut right[0])
+        } else {
+            let (left, right) = self.archetypes.split_at_mut(a);
+            (&mut right[0], &mut left[b])
+        }
+    }
+
+    /// Caches the transition from `from` to `to` by adding the component `id`,
+    /// along with the reverse transition by removing it.
+    pub(crate) fn link(&mut self, from: ArchetypeId, to: ArchetypeId, id: TypeId) {
+        self.get_by_id_mut(from)
+            .edges_mut()
+            .entry(id)
+            .or_default()
+            .add = Some(to);
+        self.get_by_id_mut(to)
+            .edges_mut()
+            .entry(id)
+            .or_default()
+            .remove = Some(from);
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -156,7 +375,7 @@ pub struct ComponentInfo {
     pub(crate) column: usize,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Edge {
     pub add: Option<ArchetypeId>,
     pub remove: Option<ArchetypeId>,
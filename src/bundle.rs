@@ -1,28 +1,65 @@
-use std::any::TypeId;
+use std::{mem::ManuallyDrop, ptr};
 
-use crate::Component;
+use crate::{component::TypeInfo, Component};
 
-pub trait Bundle {
-    fn types(&self) -> Box<[TypeId]>;
+/// A set of components that can be spawned together.
+///
+/// # Safety
+/// [`Bundle::put`] must hand out exactly one valid, owned pointer for every
+/// entry returned by [`Bundle::type_info`].
+pub unsafe trait Bundle: Send + Sync + 'static {
+    fn type_info() -> Vec<TypeInfo>;
 
-    fn components(self) -> Box<[Box<dyn Component>]>;
+    /// Passes a pointer to each component to `f`, which takes ownership of
+    /// the value behind it.
+    ///
+    /// # Safety
+    /// `f` must move or drop every value it is given.
+    unsafe fn put(self, f: &mut dyn FnMut(*mut u8, TypeInfo));
+
+    /// Builds the bundle by moving each component out of the pointer `f`
+    /// returns for its type.
+    ///
+    /// # Safety
+    /// `f` must return a pointer to a valid value of the given type, which the
+    /// bundle takes ownership of.
+    unsafe fn take(f: &mut dyn FnMut(TypeInfo) -> *mut u8) -> Self
+    where
+        Self: Sized;
+}
+
+/// Panics if a component type appears in `infos` more than once, as a bundle
+/// can only hold one value of each.
+pub(crate) fn assert_unique(infos: &[TypeInfo]) {
+    if let Some(i) =
+        (1..infos.len()).find(|&i| infos[..i].iter().any(|info| info.id() == infos[i].id()))
+    {
+        panic!(
+            "component `{}` appears more than once",
+            infos[i].type_name()
+        );
+    }
 }
 
 macro_rules! impl_bundle {
     ($($name: ident),*) => {
-        impl<$($name: Component),*> Bundle for ($($name,)*) {
-            fn types(&self) -> Box<[TypeId]> {
-                vec![$(TypeId::of::<$name>()),*].into_boxed_slice()
+        unsafe impl<$($name: Component),*> Bundle for ($($name,)*) {
+            fn type_info() -> Vec<TypeInfo> {
+                vec![$(TypeInfo::of::<$name>()),*]
             }
 
             #[allow(non_snake_case)]
-            #[allow(unused_parens)]
-            fn components(self) -> Box<[Box<dyn Component>]> {
-                let ($($name),*) = self;
-                $(let $name: Box<dyn Component> = Box::new($name);)*
+            unsafe fn put(self, f: &mut dyn FnMut(*mut u8, TypeInfo)) {
+                let ($($name,)*) = self;
+                $(
+                    let mut $name = ManuallyDrop::new($name);
+                    f(&mut *$name as *mut $name as *mut u8, TypeInfo::of::<$name>());
+                )*
+            }
 
-                vec![$($name),*].into_boxed_slice()
-            }          
+            unsafe fn take(f: &mut dyn FnMut(TypeInfo) -> *mut u8) -> Self {
+                ($(ptr::read(f(TypeInfo::of::<$name>()) as *const $name),)*)
+            }
         }
     };
 }
@@ -34,4 +71,4 @@ impl_bundle!(A, B, C, D);
 impl_bundle!(A, B, C, D, E);
 impl_bundle!(A, B, C, D, E, F);
 impl_bundle!(A, B, C, D, E, F, G);
-impl_bundle!(A, B, C, D, E, F, G, H);
\ No newline at end of file
+impl_bundle!(A, B, C, D, E, F, G, H);
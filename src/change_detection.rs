@@ -0,0 +1,140 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+/// A point in time on the [`World`](crate::World)'s change clock.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tick(u32);
+
+impl Tick {
+    pub const fn new(tick: u32) -> Self {
+        Self(tick)
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns `true` if this tick happened after `last_run`, as seen from
+    /// `this_run`. Comparing the distances to `this_run` keeps the check
+    /// correct when the clock wraps around.
+    pub fn is_newer_than(&self, last_run: Tick, this_run: Tick) -> bool {
+        let since_last_run = this_run.0.wrapping_sub(last_run.0);
+        let since_self = this_run.0.wrapping_sub(self.0);
+
+        since_last_run > since_self
+    }
+}
+
+/// When a component was added to its entity and when it was last changed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: Tick,
+    pub changed: Tick,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: Tick) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+}
+
+/// A mutable reference to a component that marks it as changed when it is
+/// dereferenced mutably.
+pub struct Mut<'a, T> {
+    value: &'a mut T,
+    changed: &'a mut Tick,
+    this_run: Tick,
+}
+
+impl<'a, T> Mut<'a, T> {
+    pub(crate) fn new(value: &'a mut T, changed: &'a mut Tick, this_run: Tick) -> Self {
+        Self {
+            value,
+            changed,
+            this_run,
+        }
+    }
+
+    /// Marks the component as changed without touching it.
+    pub fn set_changed(&mut self) {
+        *self.changed = self.this_run;
+    }
+
+    /// Returns the inner reference without marking the component as changed.
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.value
+    }
+
+    /// Marks the component as changed and returns the inner reference.
+    pub fn into_inner(self) -> &'a mut T {
+        *self.changed = self.this_run;
+        self.value
+    }
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.set_changed();
+        self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newer_than() {
+        let tick = Tick::new(5);
+
+        assert!(tick.is_newer_than(Tick::new(4), Tick::new(6)));
+        assert!(!tick.is_newer_than(Tick::new(5), Tick::new(6)));
+        assert!(!tick.is_newer_than(Tick::new(7), Tick::new(8)));
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let tick = Tick::new(2);
+
+        assert!(tick.is_newer_than(Tick::new(u32::MAX - 1), Tick::new(3)));
+        assert!(!Tick::new(u32::MAX - 2).is_newer_than(Tick::new(u32::MAX - 1), Tick::new(3)));
+    }
+
+    #[test]
+    fn test_mut() {
+        let mut value = 1;
+        let mut changed = Tick::new(0);
+
+        let mut ptr = Mut::new(&mut value, &mut changed, Tick::new(3));
+
+        assert_eq!(*ptr, 1);
+        *ptr.bypass_change_detection() = 2;
+
+        assert_eq!(changed, Tick::new(0));
+
+        let mut ptr = Mut::new(&mut value, &mut changed, Tick::new(3));
+        *ptr += 1;
+
+        assert_eq!(value, 3);
+        assert_eq!(changed, Tick::new(3));
+    }
+}
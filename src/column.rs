@@ -0,0 +1,390 @@
+use std::{
+    alloc::{self, Layout},
+    any::TypeId,
+    cell::UnsafeCell,
+    fmt,
+    ptr::{self, NonNull},
+    slice,
+};
+
+use crate::{component::TypeInfo, CloneFn, Component, ComponentTicks, Tick};
+
+/// A type-erased, contiguous vector of a single component type.
+///
+/// Values are stored inline as raw bytes laid out like a `Vec<T>`, so walking a
+/// column is a linear walk over `T` values and moving a row between columns is
+/// a `memcpy`. Each row also records when its value was added and last
+/// changed.
+pub struct Column {
+    info: TypeInfo,
+    data: NonNull<u8>,
+    ticks: Vec<UnsafeCell<ComponentTicks>>,
+    len: usize,
+    capacity: usize,
+}
+
+// Components are required to be `Send + Sync`.
+unsafe impl Send for Column {}
+unsafe impl Sync for Column {}
+
+impl Column {
+    pub fn new(info: TypeInfo) -> Self {
+        let capacity = if info.layout().size() == 0 {
+            usize::MAX
+        } else {
+            0
+        };
+
+        Self {
+            data: dangling(&info.layout()),
+            ticks: Vec::new(),
+            info,
+            len: 0,
+            capacity,
+        }
+    }
+
+    pub fn info(&self) -> &TypeInfo {
+        &self.info
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice<T: Component>(&self) -> &[T] {
+        assert_eq!(self.info.id(), TypeId::of::<T>());
+
+        unsafe { slice::from_raw_parts(self.data.as_ptr() as *const T, self.len) }
+    }
+
+    pub fn as_mut_slice<T: Component>(&mut self) -> &mut [T] {
+        assert_eq!(self.info.id(), TypeId::of::<T>());
+
+        unsafe { slice::from_raw_parts_mut(self.data.as_ptr() as *mut T, self.len) }
+    }
+
+    /// Returns the value in `row` along with its ticks.
+    pub fn get_with_ticks_mut<T: Component>(
+        &mut self,
+        row: usize,
+    ) -> Option<(&mut T, &mut ComponentTicks)> {
+        assert_eq!(self.info.id(), TypeId::of::<T>());
+
+        if row >= self.len {
+            return None;
+        }
+
+        let value = unsafe { &mut *(self.data.as_ptr() as *mut T).add(row) };
+
+        Some((value, self.ticks[row].get_mut()))
+    }
+
+    pub fn get_ticks(&self, row: usize) -> Option<ComponentTicks> {
+        self.ticks.get(row).map(|ticks| unsafe { *ticks.get() })
+    }
+
+    /// Returns a pointer to the ticks of the first row. The ticks may be
+    /// written through it as long as nothing else borrows them.
+    pub(crate) fn ticks_ptr(&self) -> NonNull<ComponentTicks> {
+        NonNull::new(UnsafeCell::raw_get(self.ticks.as_ptr())).unwrap()
+    }
+
+    /// Returns a pointer to the value in `row`.
+    ///
+    /// # Safety
+    /// `row` must be in bounds.
+    pub(crate) unsafe fn get_unchecked(&self, row: usize) -> *mut u8 {
+        debug_assert!(row < self.len);
+
+        self.data.as_ptr().add(row * self.info.layout().size())
+    }
+
+    /// Moves the value behind `ptr` onto the end of the column.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid value of the column's type, which the caller
+    /// must not use or drop afterwards.
+    pub(crate) unsafe fn push(&mut self, ptr: *const u8, ticks: ComponentTicks) {
+        self.reserve(1);
+
+        let size = self.info.layout().size();
+        ptr::copy_nonoverlapping(ptr, self.data.as_ptr().add(self.len * size), size);
+
+        self.ticks.push(UnsafeCell::new(ticks));
+        self.len += 1;
+    }
+
+    /// Drops the value in `row` and moves the value behind `ptr` into its
+    /// place, marking it as changed at `changed`.
+    ///
+    /// # Safety
+    /// `row` must be in bounds, and `ptr` must point to a valid value of the
+    /// column's type, which the caller must not use or drop afterwards.
+    pub(crate) unsafe fn replace(&mut self, row: usize, ptr: *const u8, changed: Tick) {
+        let dst = self.get_unchecked(row);
+
+        self.info.drop(dst);
+        ptr::copy_nonoverlapping(ptr, dst, self.info.layout().size());
+
+        self.ticks[row].get_mut().changed = changed;
+    }
+
+    /// Removes the value in `row` without dropping it, moving the last value
+    /// into its place.
+    ///
+    /// # Safety
+    /// `row` must be in bounds, and the caller takes ownership of the value
+    /// that was in it.
+    pub(crate) unsafe fn swap_remove_forget(&mut self, row: usize) {
+        debug_assert!(row < self.len);
+
+        let last = self.len - 1;
+
+        if row != last {
+            ptr::copy_nonoverlapping(
+                self.get_unchecked(last),
+                self.get_unchecked(row),
+                self.info.layout().size(),
+            );
+        }
+
+        self.ticks.swap_remove(row);
+        self.len = last;
+    }
+
+    /// Drops the value in `row`, moving the last value into its place.
+    pub(crate) fn swap_remove(&mut self, row: usize) {
+        assert!(row < self.len);
+
+        unsafe {
+            self.info.drop(self.get_unchecked(row));
+            self.swap_remove_forget(row);
+        }
+    }
+
+    /// The values of every row as raw bytes.
+    pub(crate) fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.data.as_ptr(), self.len * self.info.layout().size()) }
+    }
+
+    /// The ticks of every row.
+    pub(crate) fn ticks(&self) -> impl Iterator<Item = ComponentTicks> + '_ {
+        self.ticks.iter().map(|ticks| unsafe { *ticks.get() })
+    }
+
+    /// Drops every value, then fills the column with a copy of `bytes`, one row
+    /// per entry in `ticks`. Only allocates if the column has to grow.
+    ///
+    /// # Safety
+    /// `bytes` must hold `ticks.len()` valid values of the column's type, which
+    /// must be `Copy`.
+    pub(crate) unsafe fn copy_from(&mut self, bytes: &[u8], ticks: &[ComponentTicks]) {
+        self.clear();
+        self.reserve(ticks.len());
+
+        ptr::copy_nonoverlapping(bytes.as_ptr(), self.data.as_ptr(), bytes.len());
+
+        self.ticks
+            .extend(ticks.iter().map(|&ticks| UnsafeCell::new(ticks)));
+        self.len = ticks.len();
+    }
+
+    /// Creates a column with a clone of every row, made with `clone` or, if
+    /// it's `None`, by copying the bytes.
+    ///
+    /// # Safety
+    /// `clone` must clone values of the column's type. Without it, the type
+    /// must be `Copy`.
+    pub(crate) unsafe fn clone_with(&self, clone: Option<CloneFn>) -> Column {
+        let mut column = Column::new(self.info);
+        column.reserve(self.len);
+
+        let size = self.info.layout().size();
+
+        match clone {
+            Some(clone) => {
+                for row in 0..self.len {
+                    clone(
+                        self.get_unchecked(row),
+                        column.data.as_ptr().add(row * size),
+                    );
+
+                    column.ticks.push(UnsafeCell::new(*self.ticks[row].get()));
+                    column.len += 1;
+                }
+            }
+            None => {
+                let bytes = self.bytes();
+                ptr::copy_nonoverlapping(bytes.as_ptr(), column.data.as_ptr(), bytes.len());
+
+                column.ticks.extend(self.ticks().map(UnsafeCell::new));
+                column.len = self.len;
+            }
+        }
+
+        column
+    }
+
+    pub(crate) fn clear(&mut self) {
+        let len = self.len;
+
+        // Set the length first so a panicking drop can't cause a double drop.
+        self.len = 0;
+        self.ticks.clear();
+
+        for row in 0..len {
+            unsafe {
+                self.info
+                    .drop(self.data.as_ptr().add(row * self.info.layout().size()));
+            }
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        if self.len + additional <= self.capacity {
+            return;
+        }
+
+        let capacity = (self.len + additional).max(self.capacity * 2).max(4);
+        let size = self.info.layout().size();
+
+        let new_layout = array_layout(&self.info.layout(), capacity);
+
+        let data = unsafe {
+            if self.capacity == 0 {
+                alloc::alloc(new_layout)
+            } else {
+                let old_layout = array_layout(&self.info.layout(), self.capacity);
+                alloc::realloc(self.data.as_ptr(), old_layout, capacity * size)
+            }
+        };
+
+        self.data = NonNull::new(data).unwrap_or_else(|| alloc::handle_alloc_error(new_layout));
+        self.capacity = capacity;
+    }
+}
+
+impl Drop for Column {
+    fn drop(&mut self) {
+        self.clear();
+
+        if self.info.layout().size() != 0 && self.capacity != 0 {
+            unsafe {
+                alloc::dealloc(
+                    self.data.as_ptr(),
+                    array_layout(&self.info.layout(), self.capacity),
+                );
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Value<'a>(&'a TypeInfo, *const u8);
+
+        impl fmt::Debug for Value<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                unsafe { self.0.debug(self.1, f) }
+            }
+        }
+
+        f.debug_list()
+            .entries((0..self.len).map(|row| Value(&self.info, unsafe { self.get_unchecked(row) })))
+            .finish()
+    }
+}
+
+fn array_layout(layout: &Layout, n: usize) -> Layout {
+    Layout::from_size_align(layout.size() * n, layout.align()).expect("column capacity overflow")
+}
+
+fn dangling(layout: &Layout) -> NonNull<u8> {
+    NonNull::new(ptr::without_provenance_mut(layout.align())).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    const TICKS: ComponentTicks = ComponentTicks {
+        added: Tick::new(0),
+        changed: Tick::new(0),
+    };
+
+    #[test]
+    fn test_push() {
+        let mut column = Column::new(TypeInfo::of::<u64>());
+
+        for i in 0..10u64 {
+            unsafe { column.push(&i as *const u64 as *const u8, TICKS) };
+        }
+
+        assert_eq!(column.len(), 10);
+        assert_eq!(column.as_slice::<u64>(), &(0..10).collect::<Vec<_>>()[..]);
+    }
+
+    #[test]
+    fn test_swap_remove() {
+        let mut column = Column::new(TypeInfo::of::<u32>());
+
+        for i in 0..4u32 {
+            unsafe {
+                column.push(
+                    &i as *const u32 as *const u8,
+                    ComponentTicks::new(Tick::new(i)),
+                )
+            };
+        }
+
+        column.swap_remove(1);
+
+        assert_eq!(column.as_slice::<u32>(), &[0, 3, 2]);
+        assert_eq!(column.get_ticks(1), Some(ComponentTicks::new(Tick::new(3))));
+    }
+
+    #[test]
+    fn test_drop() {
+        let counter = Arc::new(());
+
+        let mut column = Column::new(TypeInfo::of::<Arc<()>>());
+
+        for _ in 0..3 {
+            let value = std::mem::ManuallyDrop::new(Arc::clone(&counter));
+            unsafe { column.push(&*value as *const Arc<()> as *const u8, TICKS) };
+        }
+
+        assert_eq!(Arc::strong_count(&counter), 4);
+
+        column.swap_remove(0);
+
+        assert_eq!(Arc::strong_count(&counter), 3);
+
+        drop(column);
+
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_zero_sized() {
+        #[derive(Debug)]
+        struct Marker;
+
+        let mut column = Column::new(TypeInfo::of::<Marker>());
+
+        for _ in 0..3 {
+            unsafe { column.push(&Marker as *const Marker as *const u8, TICKS) };
+        }
+
+        column.swap_remove(0);
+
+        assert_eq!(column.as_slice::<Marker>().len(), 2);
+    }
+}
@@ -0,0 +1,262 @@
+use std::{any::type_name, fmt};
+
+use crate::{bundle, Bundle, Component, Entities, Entity, Resource, World};
+
+/// A change to a [`World`] that is recorded now and applied later.
+pub trait Command: Send + Sync + 'static {
+    fn apply(self: Box<Self>, world: &mut World);
+}
+
+impl<F> Command for F
+where
+    F: FnOnce(&mut World) + Send + Sync + 'static,
+{
+    fn apply(self: Box<Self>, world: &mut World) {
+        (*self)(world)
+    }
+}
+
+/// Commands waiting to be applied with [`World::apply_commands`].
+#[derive(Default)]
+pub struct CommandQueue {
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl CommandQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, command: impl Command) {
+        self.commands.push(Box::new(command));
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Applies every command in the order they were pushed, leaving the queue
+    /// empty.
+    pub fn apply(&mut self, world: &mut World) {
+        world.flush();
+
+        for command in self.commands.drain(..) {
+            command.apply(world);
+        }
+    }
+}
+
+impl fmt::Debug for CommandQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandQueue")
+            .field("len", &self.commands.len())
+            .finish()
+    }
+}
+
+/// Records structural changes to a [`World`] while it's borrowed elsewhere.
+///
+/// Entities spawned through commands are reserved right away, so their handle
+/// can be used in later commands, but they only get their components when the
+/// commands are applied.
+pub struct Commands<'w, 's> {
+    queue: &'s mut CommandQueue,
+    entities: &'w Entities,
+}
+
+impl<'w, 's> Commands<'w, 's> {
+    pub fn new(queue: &'s mut CommandQueue, world: &'w World) -> Self {
+        Self {
+            queue,
+            entities: &world.entities,
+        }
+    }
+
+    /// Reserves an entity that will be spawned with no components.
+    pub fn spawn_empty(&mut self) -> EntityCommands<'_, 'w, 's> {
+        let entity = self.entities.reserve_entity_atomic();
+
+        EntityCommands {
+            entity,
+            commands: self,
+        }
+    }
+
+    /// Reserves an entity that will be spawned with the components in `bundle`.
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> EntityCommands<'_, 'w, 's> {
+        // Checked now, as applying the command would panic with the entity
+        // half spawned.
+        bundle::assert_unique(&B::type_info());
+
+        let entity = self.spawn_empty();
+        let id = entity.id();
+
+        entity.commands.add(move |world: &mut World| {
+            if world.entities.contains(id) {
+                world.spawn_at(id, bundle);
+            }
+        });
+
+        entity
+    }
+
+    /// Returns the commands for an existing or reserved entity.
+    pub fn entity(&mut self, entity: Entity) -> EntityCommands<'_, 'w, 's> {
+        EntityCommands {
+            entity,
+            commands: self,
+        }
+    }
+
+    pub fn insert_resource<R: Resource>(&mut self, value: R) {
+        self.add(move |world: &mut World| {
+            world.insert_resource(value);
+        });
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) {
+        self.add(|world: &mut World| {
+            world.remove_resource::<R>();
+        });
+    }
+
+    pub fn add(&mut self, command: impl Command) {
+        self.queue.push(command);
+    }
+}
+
+/// Records changes to a single entity, see [`Commands`].
+pub struct EntityCommands<'c, 'w, 's> {
+    entity: Entity,
+    commands: &'c mut Commands<'w, 's>,
+}
+
+impl EntityCommands<'_, '_, '_> {
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    /// Adds the component, replacing the one of the same type if there is
+    /// one. Applying it panics if the entity doesn't exist by then.
+    pub fn insert<T: Component>(&mut self, component: T) -> &mut Self {
+        let entity = self.entity;
+
+        self.commands.add(move |world: &mut World| {
+            if world.get_entity(entity).is_none() {
+                panic!(
+                    "cannot insert `{}` into entity {:?}, which doesn't exist",
+                    type_name::<T>(),
+                    entity
+                );
+            }
+
+            world.add_component(entity, component);
+        });
+
+        self
+    }
+
+    /// Removes the component `T`, if the entity still has it.
+    pub fn remove<T: Component>(&mut self) -> &mut Self {
+        let entity = self.entity;
+
+        self.commands.add(move |world: &mut World| {
+            world.remove_component::<T>(entity);
+        });
+
+        self
+    }
+
+    /// Removes the entity, if it still exists.
+    pub fn despawn(&mut self) {
+        let entity = self.entity;
+
+        self.commands.add(move |world: &mut World| {
+            world.remove_entity(entity);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Dead;
+
+    #[test]
+    fn test_spawn() {
+        let mut world = World::new();
+        let mut queue = CommandQueue::new();
+
+        let mut commands = Commands::new(&mut queue, &world);
+
+        let a = commands.spawn((Health(10),)).id();
+        let b = commands.spawn_empty().insert(Health(20)).insert(Dead).id();
+
+        assert!(world.get_entity(a).is_none());
+
+        world.apply_commands(&mut queue);
+
+        assert!(queue.is_empty());
+        assert_eq!(world.get::<Health>(a), Some(&Health(10)));
+        assert_eq!(world.get::<Health>(b), Some(&Health(20)));
+        assert_eq!(world.get::<Dead>(b), Some(&Dead));
+    }
+
+    #[test]
+    #[should_panic(expected = "appears more than once")]
+    fn test_spawn_duplicates() {
+        let world = World::new();
+        let mut queue = CommandQueue::new();
+
+        let mut commands = Commands::new(&mut queue, &world);
+        commands.spawn((Health(10), Health(20)));
+    }
+
+    #[test]
+    fn test_order() {
+        let mut world = World::new();
+        let mut queue = CommandQueue::new();
+
+        let a = world.spawn((Health(10), Dead));
+        let b = world.spawn((Health(0),));
+
+        let mut commands = Commands::new(&mut queue, &world);
+
+        commands.entity(a).remove::<Dead>().insert(Health(5));
+        commands.entity(b).despawn();
+
+        let c = commands.spawn((Health(1),)).id();
+        commands.entity(c).despawn();
+
+        world.apply_commands(&mut queue);
+
+        assert_eq!(world.get::<Health>(a), Some(&Health(5)));
+        assert_eq!(world.get::<Dead>(a), None);
+        assert!(world.get_entity(b).is_none());
+        assert!(world.get_entity(c).is_none());
+        assert_eq!(world.query::<&Health>().iter().count(), 1);
+    }
+
+    #[test]
+    fn test_resources() {
+        let mut world = World::new();
+        let mut queue = CommandQueue::new();
+
+        let mut commands = Commands::new(&mut queue, &world);
+        commands.insert_resource(3u32);
+        commands.add(|world: &mut World| *world.resource_mut::<u32>() += 1);
+
+        world.apply_commands(&mut queue);
+
+        assert_eq!(*world.resource::<u32>(), 4);
+    }
+}
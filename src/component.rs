@@ -1,9 +1,18 @@
-use std::any::Any;
+use std::{
+    alloc::Layout,
+    any::{type_name, Any, TypeId},
+    fmt, ptr,
+    sync::Mutex,
+};
+
+use crate::ComponentHooks;
 
 pub trait Component: Send + Sync + std::fmt::Debug + 'static {
     fn as_any(&self) -> &dyn Any;
 
     fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
 }
 
 impl<T: Send + Sync + std::fmt::Debug + 'static> Component for T {
@@ -14,4 +23,146 @@ impl<T: Send + Sync + std::fmt::Debug + 'static> Component for T {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// How a component type is stored and which hooks it has. Usually derived
+/// with `#[derive(Component)]`, which registers it with [`register_config`]
+/// before `main` runs, so that every world applies it the first time it sees
+/// the type. Hand-written implementations are applied with
+/// [`World::init_component`](crate::World::init_component).
+pub trait ComponentConfig: Component {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+
+    fn register_hooks(_hooks: &mut ComponentHooks) {}
+}
+
+/// The configuration of a component type, as registered with
+/// [`register_config`].
+#[derive(Clone, Copy)]
+pub(crate) struct Config {
+    type_id: TypeId,
+    pub(crate) storage: StorageType,
+    pub(crate) register_hooks: fn(&mut ComponentHooks),
+}
+
+static CONFIGS: Mutex<Vec<Config>> = Mutex::new(Vec::new());
+
+/// Registers the configuration of `T` for every world, returning whether it
+/// wasn't registered yet. Worlds that already use `T` are left alone.
+#[doc(hidden)]
+pub fn register_config<T: ComponentConfig>() -> bool {
+    let mut configs = CONFIGS.lock().unwrap();
+    let type_id = TypeId::of::<T>();
+
+    if configs.iter().any(|config| config.type_id == type_id) {
+        return false;
+    }
+
+    configs.push(Config {
+        type_id,
+        storage: T::STORAGE_TYPE,
+        register_hooks: T::register_hooks,
+    });
+
+    true
+}
+
+/// The configuration registered for the component type `type_id`, if any.
+pub(crate) fn config(type_id: TypeId) -> Option<Config> {
+    let configs = CONFIGS.lock().unwrap();
+
+    configs
+        .iter()
+        .find(|config| config.type_id == type_id)
+        .copied()
+}
+
+/// Where the components of one type are stored, chosen with
+/// [`World::register_component`](crate::World::register_component).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageType {
+    /// In archetype tables, which are the fastest to iterate. Adding or
+    /// removing the component moves the entity's whole row to another table.
+    #[default]
+    Table,
+    /// In a sparse set keyed by entity. Adding and removing the component is
+    /// cheap, which suits markers that are toggled often, but iterating it is
+    /// slower.
+    SparseSet,
+}
+
+/// Everything needed to store a component type in a type-erased column.
+#[derive(Clone, Copy)]
+pub struct TypeInfo {
+    id: TypeId,
+    layout: Layout,
+    drop: unsafe fn(*mut u8),
+    debug: unsafe fn(*const u8, &mut fmt::Formatter<'_>) -> fmt::Result,
+    type_name: &'static str,
+}
+
+impl TypeInfo {
+    pub fn of<T: Component>() -> Self {
+        unsafe fn drop_ptr<T>(ptr: *mut u8) {
+            ptr::drop_in_place(ptr as *mut T)
+        }
+
+        unsafe fn debug_ptr<T: fmt::Debug>(
+            ptr: *const u8,
+            f: &mut fmt::Formatter<'_>,
+        ) -> fmt::Result {
+            (*(ptr as *const T)).fmt(f)
+        }
+
+        Self {
+            id: TypeId::of::<T>(),
+            layout: Layout::new::<T>(),
+            drop: drop_ptr::<T>,
+            debug: debug_ptr::<T>,
+            type_name: type_name::<T>(),
+        }
+    }
+
+    pub fn id(&self) -> TypeId {
+        self.id
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn drop_fn(&self) -> unsafe fn(*mut u8) {
+        self.drop
+    }
+
+    pub fn debug_fn(&self) -> unsafe fn(*const u8, &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.debug
+    }
+
+    /// # Safety
+    /// `ptr` must point to a valid value of this type, which must not be used
+    /// afterwards.
+    pub(crate) unsafe fn drop(&self, ptr: *mut u8) {
+        (self.drop)(ptr)
+    }
+
+    /// # Safety
+    /// `ptr` must point to a valid value of this type.
+    pub(crate) unsafe fn debug(&self, ptr: *const u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.debug)(ptr, f)
+    }
+}
+
+impl fmt::Debug for TypeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.type_name)
+    }
 }
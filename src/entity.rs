@@ -1,24 +1,84 @@
-use std::{any::TypeId, mem, ops::Range};
-
-use crate::{archetype::ArchetypeId, Component, World};
-
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-pub struct Entity(usize);
+use std::{
+    alloc,
+    any::TypeId,
+    mem,
+    ops::Range,
+    sync::atomic::{AtomicIsize, Ordering},
+};
+
+use crate::{archetype::ArchetypeId, Component, ComponentTicks, TypeInfo, World};
+
+/// A handle to an entity in a [`World`].
+///
+/// An entity is made of an index into [`Entities`] and a generation. The
+/// generation is bumped every time an index is freed, so a handle kept around
+/// after its entity was removed will never resolve to whichever entity reuses
+/// the index.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Entity {
+    id: u32,
+    generation: u32,
+}
 
 impl Entity {
+    /// Panics if `n` doesn't fit in an entity index.
     pub fn from_raw(n: usize) -> Self {
-        Self(n)
+        Self::new(n, 0)
+    }
+
+    pub(crate) fn new(id: usize, generation: u32) -> Self {
+        Self {
+            id: u32::try_from(id).expect("entity index out of range"),
+            generation,
+        }
     }
 
     pub fn id(&self) -> usize {
-        self.0
+        self.id as usize
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Packs the entity into a `u64`, with the generation in the high bits.
+    pub fn to_bits(&self) -> u64 {
+        (self.generation as u64) << 32 | self.id as u64
+    }
+
+    /// Unpacks an entity previously packed with [`Entity::to_bits`].
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            id: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
     }
 }
 
+/// One past the largest index an [`Entity`] can hold.
+const MAX_INDEX: usize = u32::MAX as usize + 1;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EntityMeta {
+    pub(crate) generation: u32,
+    pub(crate) location: Location,
+}
+
+impl EntityMeta {
+    const EMPTY: Self = EntityMeta {
+        generation: 0,
+        location: Location::EMPTY,
+    };
+}
+
 #[derive(Debug)]
 pub struct Entities {
-    pub(crate) entities: Vec<Location>,
+    pub(crate) entities: Vec<EntityMeta>,
     freed: Vec<usize>,
+    /// Counts down from `freed.len()` as entities are reserved through a
+    /// shared reference. Once it goes negative, its magnitude is the number of
+    /// brand new indices reserved past `count`.
+    free_cursor: AtomicIsize,
     count: usize,
     range: Range<usize>,
 }
@@ -28,48 +88,187 @@ impl Entities {
         Self::default()
     }
 
+    /// Limits the indices handed out to `range`, and to those an [`Entity`]
+    /// can hold.
     pub fn set_range(&mut self, range: Range<usize>) {
-        self.range = range;
+        self.range = range.start..range.end.min(MAX_INDEX);
     }
 
     pub fn reserve_entity(&mut self) -> Entity {
+        debug_assert!(!self.needs_flush(), "entities must be flushed first");
+
         if self.count < self.range.start {
             self.count = self.range.start;
         }
 
         let id = self.freed.pop().unwrap_or_else(|| {
-            self.count = (self.count + 1).min(self.range.end);
+            assert!(
+                self.count < self.range.end,
+                "no entities left in the range {:?}",
+                self.range
+            );
+
+            self.count += 1;
             self.count - 1
         });
 
         if id >= self.entities.len() {
-            self.entities.resize(id + 1, Location::EMPTY);
+            self.entities.resize(id + 1, EntityMeta::EMPTY);
+        }
+
+        *self.free_cursor.get_mut() = self.freed.len() as isize;
+
+        Entity::new(id, self.entities[id].generation)
+    }
+
+    /// Reserves an entity through a shared reference. The entity only becomes
+    /// usable once [`Entities::flush`] has run.
+    pub fn reserve_entity_atomic(&self) -> Entity {
+        let n = self.free_cursor.fetch_sub(1, Ordering::Relaxed);
+
+        if n > 0 {
+            let id = self.freed[n as usize - 1];
+            Entity::new(id, self.entities[id].generation)
+        } else {
+            let id = self.count.max(self.range.start) + n.unsigned_abs();
+
+            assert!(
+                id < self.range.end,
+                "no entities left in the range {:?}",
+                self.range
+            );
+
+            Entity::new(id, 0)
+        }
+    }
+
+    /// Returns `true` if entities were reserved through
+    /// [`Entities::reserve_entity_atomic`] since the last flush.
+    pub fn needs_flush(&self) -> bool {
+        self.free_cursor.load(Ordering::Relaxed) != self.freed.len() as isize
+    }
+
+    /// Makes every entity reserved through [`Entities::reserve_entity_atomic`]
+    /// usable, placing each at the location `init` returns for it.
+    pub fn flush(&mut self, mut init: impl FnMut(Entity) -> Location) {
+        let cursor = *self.free_cursor.get_mut();
+
+        let reused = self.freed.split_off(cursor.max(0) as usize);
+
+        for id in reused {
+            let entity = Entity::new(id, self.entities[id].generation);
+            self.entities[id].location = init(entity);
+        }
+
+        if cursor < 0 {
+            let start = self.count.max(self.range.start);
+            let end = start + cursor.unsigned_abs();
+
+            self.entities.resize(end, EntityMeta::EMPTY);
+            self.count = end;
+
+            for id in start..end {
+                self.entities[id].location = init(Entity::new(id, 0));
+            }
         }
 
-        Entity(id)
+        *self.free_cursor.get_mut() = self.freed.len() as isize;
     }
 
-    pub fn free(&mut self, entity: Entity) -> Location {
-        let location = mem::replace(&mut self.entities[entity.0], Location::EMPTY);
+    /// Frees the entity's index for reuse and returns its last location, or
+    /// `None` if the handle was already stale.
+    pub fn free(&mut self, entity: Entity) -> Option<Location> {
+        if !self.contains(entity) {
+            return None;
+        }
+
+        debug_assert!(!self.needs_flush(), "entities must be flushed first");
+
+        let meta = &mut self.entities[entity.id()];
+        meta.generation = meta.generation.wrapping_add(1);
+
+        let location = mem::replace(&mut meta.location, Location::EMPTY);
 
-        self.freed.push(entity.0);
+        self.freed.push(entity.id());
+        *self.free_cursor.get_mut() = self.freed.len() as isize;
 
-        location
+        Some(location)
+    }
+
+    /// Returns `true` if the entity has been reserved and not freed since.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities
+            .get(entity.id())
+            .is_some_and(|meta| meta.generation == entity.generation)
     }
 
     pub fn get(&self, entity: Entity) -> Option<Location> {
-        if entity.0 < self.entities.len() {
-            let location = self.entities[entity.0];
+        if !self.contains(entity) {
+            return None;
+        }
 
-            if location.id != ArchetypeId::INVALID || location.row != usize::MAX {
-                Some(location)
-            } else {
-                None
-            }
+        let location = self.entities[entity.id()].location;
+
+        if location.id != ArchetypeId::INVALID || location.row != usize::MAX {
+            Some(location)
         } else {
             None
         }
     }
+
+    /// The indices waiting to be reused, the last one first.
+    pub(crate) fn freed(&self) -> &[usize] {
+        &self.freed
+    }
+
+    /// The number of indices handed out so far.
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Replaces every entity with the given generations and freed indices,
+    /// leaving each one without a location until it's set.
+    pub(crate) fn restore(&mut self, generations: Vec<u32>, freed: Vec<usize>, count: usize) {
+        self.entities = generations
+            .into_iter()
+            .map(|generation| EntityMeta {
+                generation,
+                location: Location::EMPTY,
+            })
+            .collect();
+
+        *self.free_cursor.get_mut() = freed.len() as isize;
+        self.freed = freed;
+        self.count = count;
+    }
+
+    /// Replaces every entity with a copy of `entities` and `freed`, reusing
+    /// the existing allocations where they're large enough.
+    pub(crate) fn copy_from(&mut self, entities: &[EntityMeta], freed: &[usize], count: usize) {
+        self.entities.clear();
+        self.entities.extend_from_slice(entities);
+        self.freed.clear();
+        self.freed.extend_from_slice(freed);
+
+        *self.free_cursor.get_mut() = freed.len() as isize;
+        self.count = count;
+    }
+
+    pub(crate) fn set_location(&mut self, entity: Entity, location: Location) {
+        self.entities[entity.id()].location = location;
+    }
+}
+
+impl Clone for Entities {
+    fn clone(&self) -> Self {
+        Self {
+            entities: self.entities.clone(),
+            freed: self.freed.clone(),
+            free_cursor: AtomicIsize::new(self.free_cursor.load(Ordering::Relaxed)),
+            count: self.count,
+            range: self.range.clone(),
+        }
+    }
 }
 
 impl Default for Entities {
@@ -77,8 +276,9 @@ impl Default for Entities {
         Self {
             entities: Vec::new(),
             freed: Vec::new(),
+            free_cursor: AtomicIsize::new(0),
             count: 0,
-            range: 0..usize::MAX,
+            range: 0..MAX_INDEX,
         }
     }
 }
@@ -86,41 +286,62 @@ impl Default for Entities {
 pub struct EntityBuilder<'a> {
     pub world: &'a mut World,
     pub entity: Entity,
-    pub components: Vec<(TypeId, Box<dyn Component>)>,
+    pub components: Vec<(TypeInfo, Box<dyn Component>)>,
 }
 
 impl<'a> EntityBuilder<'a> {
     pub fn build(self) -> Entity {
         let entity = self.entity;
 
-        let mut types = self
-            .components
-            .iter()
-            .map(|c| c.0)
-            .collect::<Vec<TypeId>>()
-            .into_boxed_slice();
+        let infos = self.components.iter().map(|c| c.0).collect::<Vec<_>>();
+        let types = infos.iter().map(TypeInfo::id).collect::<Vec<_>>();
 
-        types.sort_unstable();
+        let ticks = ComponentTicks::new(self.world.change_tick());
+        let infos = self.world.table_infos(infos);
+        let id = self.world.archetypes.get_or_create(infos);
+        let archetype = self.world.archetypes.get_by_id_mut(id);
 
-        let archetype = self.world.get_archetype_mut(&mut types);
+        for (info, component) in self.components {
+            let ptr = Box::into_raw(component) as *mut u8;
 
-        for (id, component) in self.components.into_iter() {
-            let info = archetype.get_component_index(&id).unwrap();
+            // Move the value out of its box, then free the box without
+            // dropping the value.
+            unsafe {
+                match self.world.sparse_sets.get_mut(info.id()) {
+                    Some(set) => set.insert(entity, ptr, ticks),
+                    None => archetype.put(info.id(), ptr, ticks),
+                }
 
-            archetype.components[info.column].push(component);
+                if info.layout().size() != 0 {
+                    alloc::dealloc(ptr, info.layout());
+                }
+            }
         }
 
-        let id = archetype.id();
-        let row = archetype.components[0].len() - 1;
+        archetype.entities.push(entity);
+
+        let row = archetype.row();
+
+        self.world
+            .entities
+            .set_location(entity, Location { row, id });
 
-        self.world.entities.entities[entity.id()] = Location { row, id };
+        self.world.on_added(entity, &types);
 
         entity
     }
 
     pub fn with<T: Component + 'static>(mut self, component: T) -> Self {
-        self.components
-            .push((TypeId::of::<T>(), Box::new(component)));
+        match self
+            .components
+            .iter_mut()
+            .find(|(info, _)| info.id() == TypeId::of::<T>())
+        {
+            Some((_, existing)) => *existing = Box::new(component),
+            None => self
+                .components
+                .push((TypeInfo::of::<T>(), Box::new(component))),
+        }
 
         self
     }
@@ -169,6 +390,52 @@ mod tests {
         let entity3 = entities.reserve_entity();
 
         assert_eq!(entity3.id(), 0);
+        assert_ne!(entity3, entity1);
+        assert!(entities.get(entity1).is_none());
+        assert!(entities.free(entity1).is_none());
+    }
+
+    #[test]
+    fn test_bits() {
+        let mut entities = Entities::new();
+
+        let entity = entities.reserve_entity();
+        entities.free(entity);
+
+        let entity = entities.reserve_entity();
+
+        assert_eq!(entity.generation(), 1);
+        assert_eq!(Entity::from_bits(entity.to_bits()), entity);
+    }
+
+    #[test]
+    fn test_reserve_atomic() {
+        let mut entities = Entities::new();
+
+        let entity1 = entities.reserve_entity();
+        let entity2 = entities.reserve_entity();
+        entities.free(entity1);
+
+        let reused = entities.reserve_entity_atomic();
+        let new = entities.reserve_entity_atomic();
+
+        assert_eq!(reused.id(), 0);
+        assert_eq!(reused.generation(), 1);
+        assert_eq!(new.id(), 2);
+        assert!(entities.needs_flush());
+        assert!(!entities.contains(new));
+
+        let mut flushed = Vec::new();
+        entities.flush(|entity| {
+            flushed.push(entity);
+            Location::EMPTY
+        });
+
+        assert_eq!(flushed, [reused, new]);
+        assert!(!entities.needs_flush());
+        assert!(entities.contains(new));
+        assert!(entities.contains(entity2));
+        assert_eq!(entities.reserve_entity().id(), 3);
     }
 
     #[test]
@@ -181,4 +448,32 @@ mod tests {
 
         assert_eq!(entity1.id(), 10);
     }
+
+    #[test]
+    fn test_range_limit() {
+        let mut entities = Entities::new();
+        assert_eq!(entities.range.end, 1 << 32);
+
+        entities.set_range(5..usize::MAX);
+        assert_eq!(entities.range, 5..1 << 32);
+    }
+
+    #[test]
+    #[should_panic(expected = "entity index out of range")]
+    fn test_from_raw_out_of_range() {
+        Entity::from_raw(1 << 32);
+    }
+
+    #[test]
+    #[should_panic(expected = "no entities left")]
+    fn test_range_atomic() {
+        let mut entities = Entities::new();
+
+        entities.set_range(10..12);
+
+        assert_eq!(entities.reserve_entity_atomic().id(), 10);
+        assert_eq!(entities.reserve_entity_atomic().id(), 11);
+
+        entities.reserve_entity_atomic();
+    }
 }
@@ -1,6 +1,6 @@
 use std::any::TypeId;
 
-use crate::{ArchetypeId, Component, Entity, Location, World};
+use crate::{ArchetypeId, Component, Entity, Location, Mut, World};
 
 #[derive(Debug, Clone)]
 pub struct EntityRef<'a> {
@@ -43,7 +43,7 @@ impl<'a> EntityRef<'a> {
     }
 
     pub fn has_component<T: Component>(&self) -> bool {
-        self.world.archetypes.get_by_id(self.archetype()).has_component::<T>()
+        has_component::<T>(self.world, self.entity, self.location)
     }
 }
 
@@ -87,29 +87,52 @@ impl<'a> EntityMut<'a> {
         get_component(self.world, self.entity, self.location)
     }
 
-    pub fn get_mut<T: Component>(&'a mut self) -> Option<&'a mut T> {
+    pub fn get_mut<T: Component>(&'a mut self) -> Option<Mut<'a, T>> {
         get_component_mut(self.world, self.entity, self.location)
     }
 
     pub fn has_component<T: Component>(&self) -> bool {
-        self.world.archetypes.get_by_id(self.archetype()).has_component::<T>()
+        has_component::<T>(self.world, self.entity, self.location)
     }
 }
 
-fn get_component<T: Component>(world: &World, _entity: Entity, location: Location) -> Option<&'_ T> {
+fn has_component<T: Component>(world: &World, entity: Entity, location: Location) -> bool {
+    match world.sparse_sets.get(TypeId::of::<T>()) {
+        Some(set) => set.contains(entity),
+        None => world
+            .archetypes
+            .get_by_id(location.archetype())
+            .has_component::<T>(),
+    }
+}
+
+fn get_component<T: Component>(world: &World, entity: Entity, location: Location) -> Option<&'_ T> {
+    if let Some(set) = world.sparse_sets.get(TypeId::of::<T>()) {
+        return set.get(entity);
+    }
+
     let archetype = world.archetypes.get_by_id(location.archetype());
-    let info = archetype.get_component_index(&TypeId::of::<T>())?;
 
-    (*archetype.components[info.column].get(location.row)?)
-        .as_any()
-        .downcast_ref::<T>()
+    archetype.column::<T>()?.as_slice::<T>().get(location.row)
 }
 
-pub fn get_component_mut<T: Component>(world: &mut World, _entity: Entity, location: Location) -> Option<&mut T> {
+pub fn get_component_mut<T: Component>(
+    world: &mut World,
+    entity: Entity,
+    location: Location,
+) -> Option<Mut<'_, T>> {
+    let this_run = world.change_tick();
+
+    if let Some(set) = world.sparse_sets.get_mut(TypeId::of::<T>()) {
+        let (value, ticks) = set.get_with_ticks_mut(entity)?;
+
+        return Some(Mut::new(value, &mut ticks.changed, this_run));
+    }
     let archetype = world.archetypes.get_by_id_mut(location.archetype());
-    let info = archetype.get_component_index(&TypeId::of::<T>())?;
 
-    (*archetype.components[info.column].get_mut(location.row)?)
-        .as_any_mut()
-        .downcast_mut::<T>()
+    let (value, ticks) = archetype
+        .column_mut::<T>()?
+        .get_with_ticks_mut(location.row)?;
+
+    Some(Mut::new(value, &mut ticks.changed, this_run))
 }
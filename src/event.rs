@@ -0,0 +1,273 @@
+use std::{fmt, marker::PhantomData, mem};
+
+use crate::{Res, ResMut};
+
+/// Something that happened, sent from one system and read by others.
+pub trait Event: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Event for T {}
+
+/// A double-buffered queue of events of type `T`, stored as a resource.
+///
+/// Every event gets an id counting up from zero. [`Events::update`] swaps the
+/// buffers once per frame, dropping the events sent two updates ago, so
+/// readers that run once per frame never miss one.
+pub struct Events<T: Event> {
+    older: Vec<T>,
+    newer: Vec<T>,
+    /// The id of the first event in `older`.
+    older_start: usize,
+    /// The id of the first event in `newer`.
+    newer_start: usize,
+    count: usize,
+}
+
+impl<T: Event> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            older: Vec::new(),
+            newer: Vec::new(),
+            older_start: 0,
+            newer_start: 0,
+            count: 0,
+        }
+    }
+}
+
+impl<T: Event> Events<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.newer.push(event);
+        self.count += 1;
+    }
+
+    /// Drops the events sent before the previous update.
+    pub fn update(&mut self) {
+        self.older = mem::take(&mut self.newer);
+        self.older_start = self.newer_start;
+        self.newer_start = self.count;
+    }
+
+    /// Drops every event, without the readers seeing them.
+    pub fn clear(&mut self) {
+        self.older.clear();
+        self.newer.clear();
+        self.older_start = self.count;
+        self.newer_start = self.count;
+    }
+
+    /// The number of events still stored.
+    pub fn len(&self) -> usize {
+        self.older.len() + self.newer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a cursor that will read every event still stored.
+    pub fn get_cursor(&self) -> EventCursor<T> {
+        EventCursor::default()
+    }
+
+    /// Returns a cursor that will only read events sent after this call.
+    pub fn get_cursor_current(&self) -> EventCursor<T> {
+        EventCursor {
+            next: self.count,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The events with an id of at least `from`.
+    fn iter_from(&self, from: usize) -> impl Iterator<Item = &T> {
+        let older = from.saturating_sub(self.older_start).min(self.older.len());
+        let newer = from.saturating_sub(self.newer_start).min(self.newer.len());
+
+        self.older[older..].iter().chain(&self.newer[newer..])
+    }
+}
+
+impl<T: Event> fmt::Debug for Events<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Events")
+            .field("len", &self.len())
+            .field("count", &self.count)
+            .finish()
+    }
+}
+
+/// Tracks which events of type `T` a reader has seen.
+pub struct EventCursor<T: Event> {
+    /// The id of the next event to read.
+    next: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Event> Default for EventCursor<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Event> EventCursor<T> {
+    /// Returns the events sent since the last read.
+    pub fn read<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let from = self.next;
+        self.next = events.count;
+
+        events.iter_from(from)
+    }
+
+    /// The number of events that haven't been read yet.
+    pub fn len(&self, events: &Events<T>) -> usize {
+        events.iter_from(self.next).count()
+    }
+
+    pub fn is_empty(&self, events: &Events<T>) -> bool {
+        self.len(events) == 0
+    }
+
+    /// Marks every event as read.
+    pub fn clear(&mut self, events: &Events<T>) {
+        self.next = events.count;
+    }
+}
+
+impl<T: Event> fmt::Debug for EventCursor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventCursor")
+            .field("next", &self.next)
+            .finish()
+    }
+}
+
+/// Reads the events of type `T` in a system. Every reader keeps its own
+/// cursor, so each one sees every event once.
+pub struct EventReader<'w, 's, T: Event> {
+    events: Res<'w, Events<T>>,
+    cursor: &'s mut EventCursor<T>,
+}
+
+impl<'w, 's, T: Event> EventReader<'w, 's, T> {
+    pub(crate) fn new(events: Res<'w, Events<T>>, cursor: &'s mut EventCursor<T>) -> Self {
+        Self { events, cursor }
+    }
+
+    /// Returns the events sent since the system last read them.
+    pub fn read(&mut self) -> impl Iterator<Item = &T> {
+        self.cursor.read(&self.events)
+    }
+
+    /// The number of events that haven't been read yet.
+    pub fn len(&self) -> usize {
+        self.cursor.len(&self.events)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cursor.is_empty(&self.events)
+    }
+
+    /// Marks every event as read.
+    pub fn clear(&mut self) {
+        self.cursor.clear(&self.events)
+    }
+}
+
+/// Sends events of type `T` from a system.
+pub struct EventWriter<'w, T: Event> {
+    events: ResMut<'w, Events<T>>,
+}
+
+impl<'w, T: Event> EventWriter<'w, T> {
+    pub(crate) fn new(events: ResMut<'w, Events<T>>) -> Self {
+        Self { events }
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.events.send(event);
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        for event in events {
+            self.events.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Collision(u32);
+
+    fn read(cursor: &mut EventCursor<Collision>, events: &Events<Collision>) -> Vec<u32> {
+        cursor.read(events).map(|event| event.0).collect()
+    }
+
+    #[test]
+    fn test_cursors() {
+        let mut events = Events::new();
+
+        let mut a = events.get_cursor();
+
+        events.send(Collision(0));
+        events.send(Collision(1));
+
+        let mut b = events.get_cursor_current();
+
+        assert_eq!(read(&mut a, &events), [0, 1]);
+        assert_eq!(read(&mut a, &events), []);
+
+        events.send(Collision(2));
+
+        assert_eq!(b.len(&events), 1);
+        assert_eq!(read(&mut a, &events), [2]);
+        assert_eq!(read(&mut b, &events), [2]);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut events = Events::new();
+
+        let mut cursor = events.get_cursor();
+
+        events.send(Collision(0));
+        events.update();
+        events.send(Collision(1));
+
+        assert_eq!(events.len(), 2);
+
+        events.update();
+
+        assert_eq!(read(&mut cursor, &events), [1]);
+
+        events.send(Collision(2));
+        events.update();
+        events.update();
+
+        assert!(events.is_empty());
+        assert_eq!(read(&mut cursor, &events), []);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut events = Events::new();
+
+        let mut cursor = events.get_cursor();
+
+        events.send(Collision(0));
+        events.update();
+        events.send(Collision(1));
+        events.clear();
+        events.send(Collision(2));
+
+        assert_eq!(read(&mut cursor, &events), [2]);
+    }
+}
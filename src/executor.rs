@@ -0,0 +1,333 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::{Condvar, Mutex},
+};
+
+use crate::{Access, System, World};
+
+/// Runs `systems` one after the other, in `order`, then applies their
+/// deferred changes in the same order.
+pub(crate) fn run_single_threaded(
+    mut systems: Vec<&mut (dyn System + 'static)>,
+    order: &[usize],
+    world: &mut World,
+) {
+    for &index in order {
+        unsafe { systems[index].run_unsafe(world) };
+    }
+
+    apply_deferred(systems, order, world);
+}
+
+/// Applies deferred changes once every system has run, so that both executors
+/// apply them at the same point and in the same order.
+fn apply_deferred(
+    mut systems: Vec<&mut (dyn System + 'static)>,
+    order: &[usize],
+    world: &mut World,
+) {
+    for &index in order {
+        systems[index].apply_deferred(world);
+    }
+}
+
+/// How far a run of [`run_multi_threaded`] has got, shared by the threads
+/// taking part in it.
+struct Progress<'a> {
+    /// The systems that haven't started yet.
+    tasks: Vec<Option<&'a mut (dyn System + 'static)>>,
+    done: Vec<bool>,
+    /// How many of its dependencies each system is still waiting on.
+    remaining: Vec<usize>,
+    finished: usize,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl Progress<'_> {
+    /// The first system in `order` that can start now: its dependencies have
+    /// finished, and its access is compatible with that of every running
+    /// system and every unfinished system before it in `order`.
+    fn next(&self, order: &[usize], accesses: &[Access]) -> Option<usize> {
+        let running = (0..self.tasks.len())
+            .filter(|&index| self.tasks[index].is_none() && !self.done[index])
+            .collect::<Vec<_>>();
+
+        let mut unfinished = Vec::new();
+
+        for &index in order.iter().filter(|&&index| !self.done[index]) {
+            let ready = self.tasks[index].is_some()
+                && self.remaining[index] == 0
+                && unfinished
+                    .iter()
+                    .chain(&running)
+                    .all(|&other| accesses[index].is_compatible(&accesses[other]));
+
+            if ready {
+                return Some(index);
+            }
+
+            unfinished.push(index);
+        }
+
+        None
+    }
+}
+
+/// Runs `systems` on the world's task pool.
+///
+/// Each thread taking part repeatedly starts the first system in `order`
+/// whose dependencies have finished and whose access is compatible with that
+/// of every running system and every unfinished system before it, until all
+/// of them have finished. Systems that only read the same data so overlap,
+/// while ones that conflict run in the same order as on a single thread, even
+/// when nothing orders them.
+pub(crate) fn run_multi_threaded(
+    mut systems: Vec<&mut (dyn System + 'static)>,
+    order: &[usize],
+    dependencies: &[Vec<usize>],
+    world: &mut World,
+) {
+    let threads = (world.task_pool().threads() + 1).min(systems.len());
+
+    if threads <= 1 {
+        return run_single_threaded(systems, order, world);
+    }
+
+    let accesses = systems
+        .iter()
+        .map(|system| system.access().clone())
+        .collect::<Vec<Access>>();
+
+    let mut dependents = vec![Vec::new(); dependencies.len()];
+
+    for (index, dependencies) in dependencies.iter().enumerate() {
+        for &dependency in dependencies {
+            dependents[dependency].push(index);
+        }
+    }
+
+    // Everything borrowing `systems` is dropped before the deferred changes
+    // get applied.
+    let panic = {
+        let progress = Mutex::new(Progress {
+            tasks: systems
+                .iter_mut()
+                .map(|system| Some(&mut **system))
+                .collect(),
+            done: vec![false; dependencies.len()],
+            remaining: dependencies
+                .iter()
+                .map(|dependencies| dependencies.len())
+                .collect(),
+            finished: 0,
+            panic: None,
+        });
+        let changed = Condvar::new();
+
+        let shared: &World = world;
+
+        shared.task_pool().broadcast(threads - 1, &|| {
+            let mut state = progress.lock().unwrap();
+
+            while state.panic.is_none() && state.finished < state.tasks.len() {
+                let Some(index) = state.next(order, &accesses) else {
+                    state = changed.wait(state).unwrap();
+                    continue;
+                };
+
+                let system = state.tasks[index].take().unwrap();
+                drop(state);
+
+                // Only systems whose access is compatible with that of every
+                // running system get started.
+                let result =
+                    panic::catch_unwind(AssertUnwindSafe(|| unsafe { system.run_unsafe(shared) }));
+
+                state = progress.lock().unwrap();
+
+                match result {
+                    Ok(()) => {
+                        state.done[index] = true;
+                        state.finished += 1;
+
+                        for &dependent in &dependents[index] {
+                            state.remaining[dependent] -= 1;
+                        }
+                    }
+                    // The other threads stop once they finish what they're
+                    // running.
+                    Err(payload) => {
+                        state.panic.get_or_insert(payload);
+                    }
+                }
+
+                changed.notify_all();
+            }
+        });
+
+        progress.into_inner().unwrap().panic
+    };
+
+    if let Some(payload) = panic {
+        panic::resume_unwind(payload);
+    }
+
+    apply_deferred(systems, order, world);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use crate::{task_pool::TaskPool, ExecutorKind, IntoSystemConfig, Query, Schedule, World};
+
+    #[derive(Debug)]
+    struct Velocity(f32);
+
+    #[derive(Debug)]
+    struct Position;
+
+    #[test]
+    fn test_conflicting_systems() {
+        let mut world = World::new();
+
+        world.spawn((Velocity(0.0), Position));
+
+        let writing = Arc::new(AtomicBool::new(false));
+        let mut schedule = Schedule::new();
+
+        for _ in 0..8 {
+            let writing = Arc::clone(&writing);
+
+            schedule.add_system(move |mut query: Query<&mut Velocity>| {
+                assert!(!writing.swap(true, Ordering::SeqCst));
+                thread::sleep(Duration::from_millis(1));
+
+                for mut velocity in query.iter_mut() {
+                    velocity.0 += 1.0;
+                }
+
+                writing.store(false, Ordering::SeqCst);
+            });
+
+            schedule.add_system(|_query: Query<&Position>| {
+                thread::sleep(Duration::from_millis(1));
+            });
+        }
+
+        schedule.run(&mut world).unwrap();
+
+        assert_eq!(world.query::<&Velocity>().iter().next().unwrap().0, 8.0);
+    }
+
+    #[test]
+    fn test_ordering() {
+        for kind in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let log = Arc::new(Mutex::new(Vec::new()));
+
+            let logger = |name: &'static str| {
+                let log = Arc::clone(&log);
+
+                move |_query: Query<&Position>| {
+                    thread::sleep(Duration::from_millis(1));
+                    log.lock().unwrap().push(name);
+                }
+            };
+
+            let mut schedule = Schedule::new();
+
+            schedule
+                .set_executor_kind(kind)
+                .add_system(logger("c").in_set("c").after("b"))
+                .add_system(logger("b").in_set("b").after("a"))
+                .add_system(logger("a").in_set("a"));
+
+            schedule.run(&mut World::new()).unwrap();
+
+            assert_eq!(*log.lock().unwrap(), ["a", "b", "c"]);
+        }
+    }
+
+    #[test]
+    fn test_shared_data_keeps_order() {
+        for kind in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let logger = |name: &'static str| {
+                let log = Arc::clone(&log);
+                move || log.lock().unwrap().push(name)
+            };
+
+            let (slow, a, b) = (logger("slow"), logger("a"), logger("b"));
+            let mut schedule = Schedule::new();
+
+            // `a` waits for `slow` to finish writing velocities, and `b`, which
+            // only shares positions with `a`, must not overtake it.
+            schedule
+                .set_executor_kind(kind)
+                .add_system(move |_query: Query<&mut Velocity>| {
+                    thread::sleep(Duration::from_millis(5));
+                    slow();
+                })
+                .add_system(move |_query: Query<(&mut Position, &Velocity)>| a())
+                .add_system(move |_query: Query<&mut Position>| b());
+
+            schedule.run(&mut World::new()).unwrap();
+
+            assert_eq!(*log.lock().unwrap(), ["slow", "a", "b"]);
+        }
+    }
+
+    #[test]
+    fn test_readers_overlap() {
+        let mut world = World::new();
+        world.set_task_pool(TaskPool::new(3));
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let overlapped = Arc::new(AtomicBool::new(false));
+        let mut schedule = Schedule::new();
+
+        for _ in 0..2 {
+            let (running, overlapped) = (Arc::clone(&running), Arc::clone(&overlapped));
+
+            // Each reader waits a while for the other one to start.
+            schedule.add_system(move |_query: Query<&Position>| {
+                running.fetch_add(1, Ordering::SeqCst);
+
+                for _ in 0..1000 {
+                    if running.load(Ordering::SeqCst) == 2 {
+                        overlapped.store(true, Ordering::SeqCst);
+                        break;
+                    }
+
+                    thread::sleep(Duration::from_millis(1));
+                }
+
+                running.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+
+        schedule.run(&mut world).unwrap();
+
+        assert!(overlapped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[should_panic(expected = "system failed")]
+    fn test_panic() {
+        let mut schedule = Schedule::new();
+
+        schedule
+            .add_system(|_query: Query<&Position>| panic!("system failed"))
+            .add_system(|_query: Query<&Position>| {});
+
+        schedule.run(&mut World::new()).unwrap();
+    }
+}
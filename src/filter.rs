@@ -0,0 +1,324 @@
+use std::{
+    any::{type_name, TypeId},
+    marker::PhantomData,
+};
+
+use crate::{query::ComponentFetch, Access, Archetype, Component, Tick, World};
+
+/// Narrows down which entities a query visits.
+///
+/// Filters first decide, once per archetype, whether an archetype can match
+/// at all. Only filters that look at change ticks or at components stored in
+/// sparse sets then check each row. Tuples of filters match when all of their
+/// elements do.
+///
+/// # Safety
+/// [`QueryFilter::update_access`] must report every component whose ticks
+/// [`QueryFilter::filter_fetch`] reads.
+pub unsafe trait QueryFilter {
+    type Fetch<'a>;
+
+    fn update_access(access: &mut Access);
+
+    fn matches(world: &World, archetype: &Archetype) -> bool;
+
+    /// # Safety
+    /// `archetype` must match the filter.
+    unsafe fn init<'a>(
+        world: &'a World,
+        archetype: &'a Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'a>;
+
+    /// # Safety
+    /// `row` must be in bounds.
+    unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool;
+}
+
+/// Matches entities that have the component `T`, without fetching it.
+pub struct With<T>(PhantomData<T>);
+
+unsafe impl<T: Component> QueryFilter for With<T> {
+    type Fetch<'a> = ComponentFetch<'a>;
+
+    fn update_access(_access: &mut Access) {}
+
+    fn matches(world: &World, archetype: &Archetype) -> bool {
+        archetype.has_component::<T>() || world.sparse_sets.contains(TypeId::of::<T>())
+    }
+
+    unsafe fn init<'a>(
+        world: &'a World,
+        archetype: &'a Archetype,
+        _last_run: Tick,
+        _this_run: Tick,
+    ) -> Self::Fetch<'a> {
+        ComponentFetch::new::<T>(world, archetype).unwrap()
+    }
+
+    unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool {
+        fetch.contains(row)
+    }
+}
+
+/// Matches entities that don't have the component `T`.
+pub struct Without<T>(PhantomData<T>);
+
+unsafe impl<T: Component> QueryFilter for Without<T> {
+    /// Only set when `T` is stored in a sparse set.
+    type Fetch<'a> = Option<ComponentFetch<'a>>;
+
+    fn update_access(_access: &mut Access) {}
+
+    fn matches(_world: &World, archetype: &Archetype) -> bool {
+        !archetype.has_component::<T>()
+    }
+
+    unsafe fn init<'a>(
+        world: &'a World,
+        archetype: &'a Archetype,
+        _last_run: Tick,
+        _this_run: Tick,
+    ) -> Self::Fetch<'a> {
+        ComponentFetch::new::<T>(world, archetype)
+    }
+
+    unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool {
+        fetch.as_ref().is_none_or(|fetch| !fetch.contains(row))
+    }
+}
+
+/// Matches entities whose component `T` was added since the query last ran.
+pub struct Added<T>(PhantomData<T>);
+
+/// Matches entities whose component `T` was added or mutably dereferenced
+/// since the query last ran.
+pub struct Changed<T>(PhantomData<T>);
+
+macro_rules! impl_tick_filter {
+    ($name: ident, $field: ident) => {
+        unsafe impl<T: Component> QueryFilter for $name<T> {
+            type Fetch<'a> = (ComponentFetch<'a>, Tick, Tick);
+
+            fn update_access(access: &mut Access) {
+                let id = TypeId::of::<T>();
+
+                assert!(
+                    !access.has_write(id),
+                    "{}<{}> conflicts with a mutable access in the same filter",
+                    stringify!($name),
+                    type_name::<T>()
+                );
+
+                access.add_read(id);
+            }
+
+            fn matches(world: &World, archetype: &Archetype) -> bool {
+                archetype.has_component::<T>() || world.sparse_sets.contains(TypeId::of::<T>())
+            }
+
+            unsafe fn init<'a>(
+                world: &'a World,
+                archetype: &'a Archetype,
+                last_run: Tick,
+                this_run: Tick,
+            ) -> Self::Fetch<'a> {
+                (
+                    ComponentFetch::new::<T>(world, archetype).unwrap(),
+                    last_run,
+                    this_run,
+                )
+            }
+
+            unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool {
+                let (ticks, last_run, this_run) = fetch;
+
+                ticks.contains(row)
+                    && (*ticks.ticks(row))
+                        .$field
+                        .is_newer_than(*last_run, *this_run)
+            }
+        }
+    };
+}
+
+impl_tick_filter!(Added, added);
+impl_tick_filter!(Changed, changed);
+
+/// Matches entities that match any of the filters in the tuple `T`.
+pub struct Or<T>(PhantomData<T>);
+
+macro_rules! impl_filter {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: QueryFilter),*> QueryFilter for ($($name,)*) {
+            type Fetch<'a> = ($($name::Fetch<'a>,)*);
+
+            fn update_access(access: &mut Access) {
+                $($name::update_access(access);)*
+            }
+
+            fn matches(world: &World, archetype: &Archetype) -> bool {
+                true $(&& $name::matches(world, archetype))*
+            }
+
+            unsafe fn init<'a>(world: &'a World, archetype: &'a Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'a> {
+                ($($name::init(world, archetype, last_run, this_run),)*)
+            }
+
+            unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool {
+                let ($($name,)*) = fetch;
+                true $(&& $name::filter_fetch($name, row))*
+            }
+        }
+
+        // Only the filters that match the archetype are initialized, and a row
+        // passes if any of those passes it.
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: QueryFilter),*> QueryFilter for Or<($($name,)*)> {
+            type Fetch<'a> = ($(Option<$name::Fetch<'a>>,)*);
+
+            fn update_access(access: &mut Access) {
+                $($name::update_access(access);)*
+            }
+
+            fn matches(world: &World, archetype: &Archetype) -> bool {
+                false $(|| $name::matches(world, archetype))*
+            }
+
+            unsafe fn init<'a>(world: &'a World, archetype: &'a Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'a> {
+                ($($name::matches(world, archetype).then(|| $name::init(world, archetype, last_run, this_run)),)*)
+            }
+
+            unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool {
+                let ($($name,)*) = fetch;
+                false $(|| $name.as_ref().is_some_and(|fetch| $name::filter_fetch(fetch, row)))*
+            }
+        }
+    };
+}
+
+impl_filter!();
+impl_filter!(A);
+impl_filter!(A, B);
+impl_filter!(A, B, C);
+impl_filter!(A, B, C, D);
+impl_filter!(A, B, C, D, E);
+impl_filter!(A, B, C, D, E, F);
+impl_filter!(A, B, C, D, E, F, G);
+impl_filter!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::World;
+
+    #[derive(Debug, PartialEq)]
+    struct Enemy(u32);
+
+    #[derive(Debug)]
+    struct Dead;
+
+    #[derive(Debug)]
+    struct Boss;
+
+    #[test]
+    fn test_without() {
+        let mut world = World::new();
+
+        world.spawn((Enemy(0),));
+        world.spawn((Enemy(1), Dead));
+        world.spawn((Enemy(2), Boss));
+
+        let query = world.query_filtered::<&Enemy, Without<Dead>>();
+
+        assert_eq!(query.iter().collect::<Vec<_>>(), [&Enemy(0), &Enemy(2)]);
+    }
+
+    #[test]
+    fn test_with() {
+        let mut world = World::new();
+
+        world.spawn((Enemy(0),));
+        world.spawn((Enemy(1), Dead));
+        world.spawn((Enemy(2), Boss, Dead));
+
+        let query = world.query_filtered::<&Enemy, (With<Dead>, Without<Boss>)>();
+
+        assert_eq!(query.iter().collect::<Vec<_>>(), [&Enemy(1)]);
+    }
+
+    #[test]
+    fn test_or() {
+        let mut world = World::new();
+
+        world.spawn((Enemy(0),));
+        world.spawn((Enemy(1), Dead));
+        world.spawn((Enemy(2), Boss));
+
+        let mut query = world.query_filtered_mut::<&mut Enemy, Or<(With<Dead>, With<Boss>)>>();
+
+        for mut enemy in query.iter_mut() {
+            enemy.0 += 10;
+        }
+
+        let query = world.query::<&Enemy>();
+
+        assert_eq!(
+            query.iter().collect::<Vec<_>>(),
+            [&Enemy(0), &Enemy(11), &Enemy(12)]
+        );
+    }
+
+    #[test]
+    fn test_added() {
+        let mut world = World::new();
+
+        world.spawn((Enemy(0),));
+        world.clear_trackers();
+
+        let a = world.spawn((Enemy(1),));
+        let b = world.spawn((Boss,));
+        world.add_component(b, Enemy(2));
+
+        let query = world.query_filtered::<&Enemy, Added<Enemy>>();
+        assert_eq!(query.iter().collect::<Vec<_>>(), [&Enemy(1), &Enemy(2)]);
+
+        world.clear_trackers();
+        world.get_mut::<Enemy>(a).unwrap().0 += 1;
+
+        assert!(world.query_filtered::<&Enemy, Added<Enemy>>().is_empty());
+    }
+
+    #[test]
+    fn test_changed() {
+        let mut world = World::new();
+
+        let a = world.spawn((Enemy(0),));
+        let b = world.spawn((Enemy(1), Dead));
+        let c = world.spawn((Enemy(2), Boss));
+
+        world.clear_trackers();
+
+        assert!(world.query_filtered::<&Enemy, Changed<Enemy>>().is_empty());
+
+        world.get_mut::<Enemy>(a).unwrap().0 += 10;
+        world.entity_mut(c).get_mut::<Enemy>().unwrap().0 += 10;
+
+        // Reading through `Mut` doesn't count as a change.
+        assert_eq!(world.get_mut::<Enemy>(b).unwrap().0, 1);
+
+        let query = world.query_filtered::<&Enemy, Changed<Enemy>>();
+        assert_eq!(query.iter().collect::<Vec<_>>(), [&Enemy(10), &Enemy(12)]);
+
+        world.clear_trackers();
+
+        for mut enemy in world.query_filtered_mut::<&mut Enemy, With<Dead>>() {
+            enemy.0 += 1;
+        }
+
+        let query = world.query_filtered::<&Enemy, Or<(Changed<Enemy>, With<Boss>)>>();
+        assert_eq!(query.iter().collect::<Vec<_>>(), [&Enemy(2), &Enemy(12)]);
+    }
+}
@@ -0,0 +1,242 @@
+use std::ops::Deref;
+
+use crate::{Commands, Entity, World};
+
+/// The entity this entity is a child of, set with [`World::set_parent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parent(pub(crate) Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// The children of an entity, in the order they were added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Children(pub(crate) Vec<Entity>);
+
+impl Deref for Children {
+    type Target = [Entity];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Iterates the parent of an entity, then its grandparent, and so on up to
+/// the root.
+pub struct Ancestors<'w> {
+    world: &'w World,
+    next: Option<Entity>,
+}
+
+impl<'w> Ancestors<'w> {
+    pub(crate) fn new(world: &'w World, entity: Entity) -> Self {
+        Self {
+            world,
+            next: world.get::<Parent>(entity).map(Parent::get),
+        }
+    }
+}
+
+impl Iterator for Ancestors<'_> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entity = self.next?;
+        self.next = self.world.get::<Parent>(entity).map(Parent::get);
+
+        Some(entity)
+    }
+}
+
+/// Iterates every descendant of an entity depth-first, visiting each entity
+/// before its children.
+pub struct Descendants<'w> {
+    world: &'w World,
+    stack: Vec<Entity>,
+}
+
+impl<'w> Descendants<'w> {
+    pub(crate) fn new(world: &'w World, entity: Entity) -> Self {
+        let mut descendants = Self {
+            world,
+            stack: Vec::new(),
+        };
+
+        descendants.push_children(entity);
+        descendants
+    }
+
+    fn push_children(&mut self, entity: Entity) {
+        if let Some(children) = self.world.get::<Children>(entity) {
+            self.stack.extend(children.iter().rev());
+        }
+    }
+}
+
+impl Iterator for Descendants<'_> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entity = self.stack.pop()?;
+        self.push_children(entity);
+
+        Some(entity)
+    }
+}
+
+/// Takes a child that loses its [`Parent`] out of the parent's [`Children`].
+pub(crate) fn on_remove_parent(world: &World, child: Entity, commands: &mut Commands) {
+    let parent = world.get::<Parent>(child).unwrap().get();
+
+    commands.add(move |world: &mut World| world.remove_child(parent, child));
+}
+
+/// Removes the [`Parent`] of every child of an entity that loses its
+/// [`Children`], for example because it was removed.
+pub(crate) fn on_remove_children(world: &World, parent: Entity, commands: &mut Commands) {
+    for &child in world.get::<Children>(parent).unwrap().iter() {
+        commands.add(move |world: &mut World| {
+            if world.get::<Parent>(child) == Some(&Parent(parent)) {
+                world.remove_component::<Parent>(child);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Tank;
+
+    #[derive(Debug)]
+    struct Turret;
+
+    #[test]
+    fn test_set_parent() {
+        let mut world = World::new();
+
+        let a = world.spawn((Tank,));
+        let b = world.spawn((Tank,));
+        let turret = world.spawn((Turret,));
+
+        world.set_parent(turret, a);
+
+        assert_eq!(world.get::<Parent>(turret), Some(&Parent(a)));
+        assert_eq!(&**world.get::<Children>(a).unwrap(), [turret]);
+
+        world.set_parent(turret, b);
+
+        assert_eq!(world.get::<Parent>(turret), Some(&Parent(b)));
+        assert_eq!(world.get::<Children>(a), None);
+        assert_eq!(&**world.get::<Children>(b).unwrap(), [turret]);
+
+        assert_eq!(world.remove_parent(turret), Some(b));
+        assert_eq!(world.remove_parent(turret), None);
+        assert_eq!(world.get::<Children>(b), None);
+    }
+
+    #[test]
+    fn test_traversal() {
+        let mut world = World::new();
+
+        let [root, a, b, c, d] = [(); 5].map(|_| world.spawn((Tank,)));
+
+        world.set_parent(a, root);
+        world.set_parent(b, a);
+        world.set_parent(c, root);
+        world.set_parent(d, a);
+
+        assert_eq!(world.ancestors(b).collect::<Vec<_>>(), [a, root]);
+        assert_eq!(world.ancestors(root).count(), 0);
+        assert_eq!(world.descendants(root).collect::<Vec<_>>(), [a, b, d, c]);
+    }
+
+    #[test]
+    fn test_despawn() {
+        let mut world = World::new();
+
+        let [root, a, b, c] = [(); 4].map(|_| world.spawn((Tank,)));
+
+        world.set_parent(a, root);
+        world.set_parent(b, a);
+        world.set_parent(c, root);
+
+        world.remove_entity(root);
+
+        assert_eq!(world.get::<Parent>(a), None);
+        assert_eq!(world.get::<Parent>(c), None);
+        assert_eq!(world.get::<Parent>(b), Some(&Parent(a)));
+
+        world.set_parent(c, b);
+
+        assert!(world.despawn_recursive(a));
+
+        assert!(world.get_entity(a).is_none());
+        assert!(world.get_entity(b).is_none());
+        assert!(world.get_entity(c).is_none());
+    }
+
+    #[test]
+    fn test_despawn_child() {
+        let mut world = World::new();
+
+        let root = world.spawn((Tank,));
+        let a = world.spawn((Turret,));
+        let b = world.spawn((Turret,));
+
+        world.set_parent(a, root);
+        world.set_parent(b, root);
+
+        world.despawn_recursive(a);
+
+        assert_eq!(&**world.get::<Children>(root).unwrap(), [b]);
+    }
+
+    #[test]
+    fn test_user_hooks() {
+        struct Removed(u32);
+
+        fn on_remove_parent(_: &World, _: Entity, commands: &mut Commands) {
+            commands.add(|world: &mut World| world.resource_mut::<Removed>().0 += 1);
+        }
+
+        let mut world = World::new();
+        world.insert_resource(Removed(0));
+
+        // Hooks registered before and after the hierarchy is first used both
+        // leave it working.
+        world
+            .register_component_hooks::<Parent>()
+            .on_add(|_, _, _| {});
+
+        let root = world.spawn((Tank,));
+        let a = world.spawn((Turret,));
+        world.set_parent(a, root);
+
+        world
+            .register_component_hooks::<Parent>()
+            .on_remove(on_remove_parent);
+
+        world.remove_entity(root);
+
+        assert_eq!(world.get::<Parent>(a), None);
+        assert_eq!(world.resource::<Removed>().0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "descendant")]
+    fn test_cycle() {
+        let mut world = World::new();
+
+        let a = world.spawn((Tank,));
+        let b = world.spawn((Tank,));
+
+        world.set_parent(b, a);
+        world.set_parent(a, b);
+    }
+}
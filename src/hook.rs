@@ -0,0 +1,64 @@
+use std::any::type_name;
+
+use crate::{Commands, Entity, World};
+
+/// Called with the entity whose component was added, inserted or is about to
+/// be removed. The commands are applied once the change that triggered the
+/// hook is complete.
+pub type ComponentHook = fn(&World, Entity, &mut Commands);
+
+/// The hooks registered for one component type.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ComponentHooks {
+    pub(crate) on_add: Option<ComponentHook>,
+    pub(crate) on_insert: Option<ComponentHook>,
+    pub(crate) on_remove: Option<ComponentHook>,
+    pub(crate) type_name: &'static str,
+}
+
+impl ComponentHooks {
+    pub(crate) fn of<T>() -> Self {
+        Self {
+            type_name: type_name::<T>(),
+            ..Self::default()
+        }
+    }
+
+    /// Runs when the component is added to an entity that didn't have it.
+    pub fn on_add(&mut self, hook: ComponentHook) -> &mut Self {
+        assert!(
+            self.on_add.is_none(),
+            "component `{}` already has an `on_add` hook",
+            self.type_name
+        );
+
+        self.on_add = Some(hook);
+        self
+    }
+
+    /// Runs every time the component is put on an entity, after `on_add` if
+    /// the entity didn't have it, or after the old value was replaced.
+    pub fn on_insert(&mut self, hook: ComponentHook) -> &mut Self {
+        assert!(
+            self.on_insert.is_none(),
+            "component `{}` already has an `on_insert` hook",
+            self.type_name
+        );
+
+        self.on_insert = Some(hook);
+        self
+    }
+
+    /// Runs before the component is removed from an entity, or the entity is
+    /// removed, while the component can still be read.
+    pub fn on_remove(&mut self, hook: ComponentHook) -> &mut Self {
+        assert!(
+            self.on_remove.is_none(),
+            "component `{}` already has an `on_remove` hook",
+            self.type_name
+        );
+
+        self.on_remove = Some(hook);
+        self
+    }
+}
@@ -1,25 +1,72 @@
-#![feature(negative_impls)]
+// Lets the derive macros refer to this crate as `rusty_ecs` from inside it.
+extern crate self as rusty_ecs;
 
+mod access;
 mod archetype;
 mod bundle;
+mod change_detection;
+mod column;
+mod command;
 mod component;
 mod entity;
 mod entity_ref;
+mod event;
+mod executor;
+mod filter;
+mod hierarchy;
+mod hook;
 mod query;
+mod registry;
+mod resource;
+mod schedule;
+mod snapshot;
 mod sparse_set;
+mod system;
+mod task_pool;
+mod value;
 mod world;
-
-pub use archetype::{Archetype, ArchetypeId, Archetypes, Edge};
-pub use component::Component;
+mod world_snapshot;
+
+pub use access::Access;
+pub use archetype::{Archetype, ArchetypeGeneration, ArchetypeId, Archetypes, Edge};
+pub use bundle::Bundle;
+pub use change_detection::{ComponentTicks, Mut, Tick};
+pub use column::Column;
+pub use command::{Command, CommandQueue, Commands, EntityCommands};
+pub use component::{register_config, Component, ComponentConfig, StorageType, TypeInfo};
 pub use entity::{Entities, Entity, EntityBuilder, Location};
 pub use entity_ref::{EntityMut, EntityRef};
-pub use sparse_set::{SparseSet, SparseArray};
-pub use world::World;
+pub use event::{Event, EventCursor, EventReader, EventWriter, Events};
+pub use filter::{Added, Changed, Or, QueryFilter, With, Without};
+pub use hierarchy::{Ancestors, Children, Descendants, Parent};
+pub use hook::{ComponentHook, ComponentHooks};
+pub use query::{ComponentsQuery, Query, QueryIter, QueryState, ReadOnlyQuery, DEFAULT_BATCH_SIZE};
+pub use registry::{
+    CloneFn, ComponentDescriptor, ComponentId, ComponentRegistry, DebugFn, DefaultFn, FromValueFn,
+    ToValueFn,
+};
+pub use resource::{Res, ResMut, Resource, Resources};
+pub use rusty_ecs_derive::{Bundle, Component};
+pub use schedule::{
+    ExecutorKind, IntoLabel, IntoSystemConfig, Label, Schedule, ScheduleError, SystemConfig,
+    SystemSet,
+};
+pub use snapshot::{SnapshotComponent, SnapshotError};
+pub use sparse_set::{ComponentSparseSet, SparseArray, SparseSet, SparseSets};
+pub use system::{
+    FunctionSystem, IntoSystem, IsFunctionSystem, System, SystemMeta, SystemParam,
+    SystemParamFunction, SystemParamItem,
+};
+pub use value::Value;
+pub use world::{CloneError, World, WorldId};
+pub use world_snapshot::WorldSnapshot;
 
 #[cfg(test)]
 mod tests {
     #![allow(dead_code)]
 
+    use std::any::TypeId;
+
     use super::*;
 
     #[derive(Debug, Copy, Clone)]
@@ -80,7 +127,7 @@ mod tests {
         world.add_component(a, Label("Hello".into()));
         world.add_component(a, Marker);
 
-        //world.remove_component::<Velocity>(c);
+        world.remove_component::<Velocity>(c);
 
         println!("{world:#?}");
 
@@ -114,23 +161,50 @@ mod tests {
         println!("{i}");*/
     }
 
-    //#[test]
+    #[test]
     fn remove_entities() {
         let mut world = World::new();
 
         let a = world.create_entity().with(Label("Steve".into())).build();
         let b = world.create_entity().with(Label("Tag".into())).build();
+        let c = world.create_entity().with(Label("Bob".into())).build();
 
-        world.remove_entity(a);
-        world.remove_entity(b);
+        assert!(world.remove_entity(a));
+        assert!(!world.remove_entity(a));
+
+        assert_eq!(world.get::<Label>(a), None);
+        assert_eq!(world.get::<Label>(b), Some(&Label("Tag".into())));
+        assert_eq!(world.get::<Label>(c), Some(&Label("Bob".into())));
+        assert_eq!(world.query::<&Label>().iter().count(), 2);
 
-        println!("{:?}", world.get::<Label>(a));
+        assert!(world.remove_entity(b));
+        assert_eq!(world.get::<Label>(c), Some(&Label("Bob".into())));
+        assert_eq!(
+            world.query::<&Label>().iter().collect::<Vec<_>>(),
+            [&Label("Bob".into())]
+        );
 
         world.create_entity().with(Rotation(45.0)).build();
 
         println!("{world:#?}");
     }
 
+    #[test]
+    fn remove_entity_drops_components() {
+        use std::sync::Arc;
+
+        let counter = Arc::new(());
+
+        let mut world = World::new();
+        let a = world.spawn((Arc::clone(&counter),));
+
+        assert_eq!(Arc::strong_count(&counter), 2);
+
+        world.remove_entity(a);
+
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
     //#[test]
     fn create_entities() {
         let mut world = World::new();
@@ -201,17 +275,29 @@ mod tests {
 
         println!("{:?}", archetype.has_component::<Velocity>());
 
-        println!("{:#?}", world.query::<Velocity>());
-        println!("{:#?}", world.query::<Rotation>());
-        println!("{:#?}", world.query::<Label>());
+        println!(
+            "{:#?}",
+            world.query::<&Velocity>().iter().collect::<Vec<_>>()
+        );
+        println!(
+            "{:#?}",
+            world.query::<&Rotation>().iter().collect::<Vec<_>>()
+        );
+        println!("{:#?}", world.query::<&Label>().iter().collect::<Vec<_>>());
 
         //let vels = world.query::<Velocity>();
 
-        let mut rotations = world.query_mut::<Rotation>();
+        let mut rotations = world
+            .query_mut::<&mut Rotation>()
+            .into_iter()
+            .collect::<Vec<_>>();
 
         rotations[0].0 *= 0.2;
 
-        println!("{:#?}", world.query::<Rotation>());
+        println!(
+            "{:#?}",
+            world.query::<&Rotation>().iter().collect::<Vec<_>>()
+        );
 
         //for (velocity, rotation) in vels.iter().zip(rotations.iter()) {
         //println!("{:?}", (velocity, rotation));
@@ -221,18 +307,125 @@ mod tests {
         //println!("{:?}", world.archetypes.archetypes.len());
     }
 
+    #[test]
+    fn stale_entity() {
+        let mut world = World::new();
+
+        let a = world.spawn((A(1),));
+        world.remove_entity(a);
+
+        let b = world.spawn((A(2),));
+
+        assert_eq!(a.id(), b.id());
+        assert!(world.get::<A>(a).is_none());
+        assert!(world.get_entity(a).is_none());
+        assert_eq!(world.get::<A>(b), Some(&A(2)));
+    }
+
+    #[test]
+    fn remove_component() {
+        let mut world = World::new();
+
+        let a = world.spawn((A(1), Label("a".into())));
+        let b = world.spawn((A(2), Label("b".into())));
+
+        assert_eq!(world.remove_component::<A>(a), Some(A(1)));
+        assert_eq!(world.remove_component::<A>(a), None);
+
+        assert_eq!(world.get::<A>(a), None);
+        assert_eq!(world.get::<Label>(a), Some(&Label("a".into())));
+        assert_eq!(world.get::<A>(b), Some(&A(2)));
+        assert_eq!(world.get::<Label>(b), Some(&Label("b".into())));
+
+        let from = world.entity(b).archetype();
+        let to = world.entity(a).archetype();
+
+        let edge = &world.archetypes.get_by_id(from).edges()[&TypeId::of::<A>()];
+        assert_eq!(edge.remove, Some(to));
+
+        let edge = &world.archetypes.get_by_id(to).edges()[&TypeId::of::<A>()];
+        assert_eq!(edge.add, Some(from));
+
+        assert_eq!(world.remove_component::<A>(b), Some(A(2)));
+        assert_eq!(world.entity(b).archetype(), to);
+
+        world.add_component(b, A(3));
+        assert_eq!(world.entity(b).archetype(), from);
+        assert_eq!(world.get::<A>(b), Some(&A(3)));
+    }
+
+    #[test]
+    fn table_storage() {
+        let mut world = World::new();
+
+        let a = world.spawn((Label("a".into()), A(1)));
+        let b = world.spawn((A(2), Label("b".into())));
+        let c = world
+            .create_entity()
+            .with(A(3))
+            .with(Label("c".into()))
+            .build();
+
+        assert_eq!(world.archetypes.archetypes.len(), 1);
+
+        let archetype = world.archetypes.get_by_id(world.entity(a).archetype());
+        assert_eq!(archetype.get_component::<A>(), &[A(1), A(2), A(3)]);
+
+        world.add_component(b, Marker);
+        world.add_component(a, Marker);
+
+        assert_eq!(world.get::<Label>(a), Some(&Label("a".into())));
+        assert_eq!(world.get::<Label>(b), Some(&Label("b".into())));
+        assert_eq!(world.get::<Label>(c), Some(&Label("c".into())));
+
+        assert_eq!(world.remove_component::<Label>(b), Some(Label("b".into())));
+        assert_eq!(world.get::<A>(b), Some(&A(2)));
+        assert_eq!(world.get::<Label>(a), Some(&Label("a".into())));
+    }
+
+    #[test]
+    #[should_panic]
+    fn duplicate_components() {
+        let mut world = World::new();
+
+        world.spawn((A(1), A(2)));
+    }
+
+    #[test]
+    fn duplicate_components_leave_no_entity() {
+        let mut world = World::new();
+        world.register_component::<Marker>(StorageType::SparseSet);
+
+        let spawn = |world: &mut World, f: fn(&mut World)| {
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(world))).unwrap_err();
+        };
+
+        spawn(&mut world, |world| {
+            world.spawn((A(1), A(2)));
+        });
+        spawn(&mut world, |world| {
+            world.spawn((Marker, A(1), Marker));
+        });
+
+        assert_eq!(world.entities.count(), 0);
+        assert_eq!(world.spawn((A(3),)).id(), 0);
+    }
+
     #[test]
     fn query() {
         let mut world = World::new();
 
         world.create_entity().with(A(10)).build();
 
-        assert_eq!(world.query::<A>(), [&A(10)]);
+        assert_eq!(world.query::<&A>().iter().collect::<Vec<_>>(), [&A(10)]);
 
         world.create_entity().with(A(21)).build();
         world.create_entity().with(A(42)).build();
 
-        assert_eq!(world.query::<A>(), [&A(10), &A(21), &A(42)]);
+        assert_eq!(
+            world.query::<&A>().iter().collect::<Vec<_>>(),
+            [&A(10), &A(21), &A(42)]
+        );
     }
 
     #[test]
@@ -242,18 +435,444 @@ mod tests {
         world.create_entity().with(A(10)).build();
         world.create_entity().with(Label("abc".into())).build();
 
-        assert_eq!(world.query::<A>()[0], &A(10));
+        assert_eq!(world.query::<&A>().iter().next(), Some(&A(10)));
 
-        let mut a = &mut world.query_mut::<A>()[0];
+        let mut a = world.query_mut::<&mut A>().into_iter().next().unwrap();
 
         a.0 += 5;
 
-        assert_eq!(world.query::<A>()[0], &A(15));
+        assert_eq!(world.query::<&A>().iter().next(), Some(&A(15)));
+
+        assert_eq!(
+            world.query::<&Label>().iter().next(),
+            Some(&Label("abc".into()))
+        );
+
+        world
+            .query_mut::<&mut Label>()
+            .into_iter()
+            .next()
+            .unwrap()
+            .0 += "efg";
+
+        assert_eq!(
+            world.query::<&Label>().iter().next(),
+            Some(&Label("abcefg".into()))
+        );
+    }
+
+    #[test]
+    fn resources() {
+        #[derive(Debug, PartialEq)]
+        struct DeltaTime(f32);
+
+        let mut world = World::new();
+
+        assert!(!world.contains_resource::<DeltaTime>());
+        assert_eq!(world.insert_resource(DeltaTime(0.1)), None);
+        assert_eq!(world.insert_resource(DeltaTime(0.2)), Some(DeltaTime(0.1)));
+
+        world.resource_mut::<DeltaTime>().0 *= 2.0;
+
+        assert_eq!(world.resource::<DeltaTime>(), &DeltaTime(0.4));
+        assert_eq!(world.remove_resource::<DeltaTime>(), Some(DeltaTime(0.4)));
+        assert_eq!(world.get_resource::<DeltaTime>(), None);
+    }
+
+    #[test]
+    fn hooks() {
+        #[derive(Debug)]
+        struct Body;
+
+        #[derive(Debug, PartialEq)]
+        struct Handle(usize);
+
+        #[derive(Default)]
+        struct Physics(Vec<Entity>);
+
+        let mut world = World::new();
+        world.insert_resource(Physics::default());
+
+        world
+            .register_component_hooks::<Body>()
+            .on_add(|world, entity, commands| {
+                let handle = world.resource::<Physics>().0.len();
+
+                commands.entity(entity).insert(Handle(handle));
+                commands.add(move |world: &mut World| {
+                    world.resource_mut::<Physics>().0.push(entity);
+                });
+            })
+            .on_remove(|_, entity, commands| {
+                commands.add(move |world: &mut World| {
+                    world.resource_mut::<Physics>().0.retain(|&e| e != entity);
+                });
+            });
+
+        let a = world.spawn((Body,));
+        let b = world.create_entity().with(Body).build();
+        let c = world.spawn((A(0),));
+        world.add_component(c, Body);
+
+        assert_eq!(world.resource::<Physics>().0, [a, b, c]);
+        assert_eq!(world.get::<Handle>(b), Some(&Handle(1)));
+
+        world.remove_component::<Body>(a);
+        world.remove_entity(b);
+
+        assert_eq!(world.resource::<Physics>().0, [c]);
+    }
+
+    #[test]
+    fn sparse_storage() {
+        #[derive(Debug, PartialEq)]
+        struct Selected(u32);
+
+        let mut world = World::new();
+        world.register_component::<Selected>(StorageType::SparseSet);
+
+        let a = world.spawn((A(0), Selected(1)));
+        let b = world.spawn((A(1),));
+        let c = world.create_entity().with(A(2)).with(Selected(3)).build();
+
+        let archetype = world.entity(b).archetype();
+
+        world.add_component(b, Selected(2));
+
+        assert_eq!(world.entity(a).archetype(), archetype);
+        assert_eq!(world.entity(b).archetype(), archetype);
+        assert!(world.entity(b).has_component::<Selected>());
+
+        world.remove_component::<Selected>(a);
+        world.get_mut::<Selected>(c).unwrap().0 += 10;
+
+        let mut selected = world
+            .query::<(Entity, &A, &Selected)>()
+            .iter()
+            .map(|(entity, a, selected)| (entity, a.0, selected.0))
+            .collect::<Vec<_>>();
+        selected.sort_by_key(|&(_, a, _)| a);
+
+        assert_eq!(selected, [(b, 1, 2), (c, 2, 13)]);
+
+        let unselected = world.query_filtered::<Entity, Without<Selected>>();
+        assert_eq!(unselected.iter().collect::<Vec<_>>(), [a]);
+
+        world.remove_entity(c);
+
+        assert_eq!(world.query::<&Selected>().iter().count(), 1);
+    }
+
+    #[test]
+    fn component_registry() {
+        #[derive(Debug, Clone, Default)]
+        struct Name(String);
+
+        let mut world = World::new();
+        world.register_component::<Name>(StorageType::SparseSet);
+        world.register_clone::<Name>();
+
+        world.spawn((A(1), Name("a".into())));
+
+        let components = world.components();
+        let a = components.component_id::<A>().unwrap();
+        let name = components.component_id::<Name>().unwrap();
+
+        assert_ne!(a, name);
+        assert_eq!(
+            components.get(a).unwrap().storage_type(),
+            StorageType::Table
+        );
+
+        let descriptor = components.get(name).unwrap();
+
+        assert_eq!(descriptor.storage_type(), StorageType::SparseSet);
+        assert!(descriptor.clone_fn().is_some());
+        assert!(descriptor.default_fn().is_none());
+
+        let debug = format!("{world:?}");
+
+        assert!(debug.contains(std::any::type_name::<A>()));
+        assert!(!debug.contains("TypeId"));
+    }
+
+    #[test]
+    fn clone_world() {
+        let mut world = World::new();
+        world.register_component::<Marker>(StorageType::SparseSet);
+        world.register_clone::<Label>();
+        world.register_pod::<Rotation>();
+        world.register_pod::<Marker>();
+        world.register_clone::<Parent>();
+        world.register_clone::<Children>();
+
+        let a = world.spawn((Label("a".into()), Rotation(1.0)));
+        let b = world.spawn((Label("b".into()), Marker));
+        let freed = world.spawn((Rotation(0.0),));
+        world.set_parent(b, a);
+        world.remove_entity(freed);
+
+        let mut fork = world.clone_world().unwrap();
+
+        assert_eq!(fork.get::<Label>(a), Some(&Label("a".into())));
+        assert_eq!(fork.entity(b).archetype(), world.entity(b).archetype());
+        assert_eq!(fork.entity(b).row(), world.entity(b).row());
+        assert!(fork.entity(b).has_component::<Marker>());
+        assert_eq!(fork.ancestors(b).collect::<Vec<_>>(), [a]);
+        assert!(fork.get_entity(freed).is_none());
+
+        assert_eq!(fork.spawn((Rotation(2.0),)), world.spawn((Rotation(2.0),)));
+
+        fork.get_mut::<Label>(a).unwrap().0.push('!');
+        fork.despawn_recursive(a);
+
+        assert_eq!(world.get::<Label>(a), Some(&Label("a".into())));
+        assert_eq!(
+            world.get::<Children>(a).map(|children| children.len()),
+            Some(1)
+        );
+
+        world.spawn((A(0),));
+
+        assert_eq!(
+            world.clone_world().err(),
+            Some(CloneError::NotClonable(std::any::type_name::<A>()))
+        );
+    }
+
+    #[test]
+    fn derive_bundle() {
+        #[derive(Bundle)]
+        struct Motion {
+            velocity: Velocity,
+            rotation: Rotation,
+        }
+
+        #[derive(Bundle)]
+        struct Player {
+            label: Label,
+            #[bundle]
+            motion: Motion,
+        }
+
+        #[derive(Bundle)]
+        struct Tagged(Marker, Label);
+
+        let mut world = World::new();
+
+        let player = world.spawn(Player {
+            label: Label("player".into()),
+            motion: Motion {
+                velocity: Velocity { x: 1.0, y: 0.0 },
+                rotation: Rotation(0.5),
+            },
+        });
+        let tagged = world.spawn(Tagged(Marker, Label("tagged".into())));
+
+        assert_eq!(Player::type_info().len(), 3);
+        assert_eq!(world.get::<Label>(player), Some(&Label("player".into())));
+        assert_eq!(world.get::<Rotation>(player).unwrap().0, 0.5);
+        assert_eq!(world.get::<Velocity>(player).unwrap().x, 1.0);
+        assert!(world.entity(tagged).has_component::<Marker>());
+        assert_eq!(world.query::<&Label>().iter().count(), 2);
+    }
+
+    #[test]
+    fn derive_component() {
+        #[derive(Debug, Component)]
+        #[component(storage = "sparse_set", on_add = on_add, on_remove = on_remove)]
+        struct Target(Entity);
+
+        #[derive(Debug, Component)]
+        struct Health(u32);
+
+        #[derive(Debug)]
+        struct Targeted;
+
+        fn on_add(world: &World, entity: Entity, commands: &mut Commands) {
+            let target = world.get::<Target>(entity).unwrap().0;
+            commands.entity(target).insert(Targeted);
+        }
+
+        fn on_remove(world: &World, entity: Entity, commands: &mut Commands) {
+            let target = world.get::<Target>(entity).unwrap().0;
+            commands.entity(target).remove::<Targeted>();
+        }
+
+        let mut world = World::new();
+
+        let enemy = world.spawn((Health(10),));
+        let player = world.spawn((Target(enemy),));
+
+        assert_eq!(world.storage_type::<Target>(), StorageType::SparseSet);
+        assert_eq!(world.storage_type::<Health>(), StorageType::Table);
+
+        assert!(world.entity(enemy).has_component::<Targeted>());
+
+        world.remove_component::<Target>(player);
+
+        assert!(!world.entity(enemy).has_component::<Targeted>());
+    }
+
+    #[test]
+    fn init_component() {
+        #[derive(Debug)]
+        struct Frozen;
+
+        impl ComponentConfig for Frozen {
+            const STORAGE_TYPE: StorageType = StorageType::SparseSet;
+        }
+
+        let mut world = World::new();
+        world.init_component::<Frozen>();
+        world.spawn((Frozen,));
+
+        // Worlds that see the type later apply its configuration by themselves.
+        let mut other = World::new();
+        other.spawn((Frozen,));
+
+        assert_eq!(world.storage_type::<Frozen>(), StorageType::SparseSet);
+        assert_eq!(other.storage_type::<Frozen>(), StorageType::SparseSet);
+    }
+
+    #[test]
+    fn bundles() {
+        #[derive(Bundle)]
+        struct Tagged {
+            label: Label,
+            marker: Marker,
+        }
+
+        let mut world = World::new();
+        world.register_component::<Marker>(StorageType::SparseSet);
+
+        let a = world.spawn((A(0),));
+        let b = world.spawn((A(1),));
+        let from = world.entity(a).archetype();
+
+        world.insert_bundle(a, (Label("a".into()), Rotation(1.0), Marker));
+        let to = world.entity(a).archetype();
+
+        let archetype = world.archetypes.get_by_id(from);
+        let edge = &archetype.bundle_edges()[&TypeId::of::<(Label, Rotation, Marker)>()];
+
+        assert_eq!(edge.add, Some(to));
+        assert!(archetype.edges().is_empty());
+        assert_eq!(world.archetypes.archetypes.len(), 2);
+        assert!(world.entity(a).has_component::<Marker>());
+
+        // Components the entity has are replaced, without moving it.
+        world.insert_bundle(a, (A(2), Label("b".into())));
+
+        assert_eq!(world.entity(a).archetype(), to);
+        assert_eq!(world.get::<A>(a), Some(&A(2)));
+        assert_eq!(world.get::<Label>(a), Some(&Label("b".into())));
+
+        world.insert_bundle(b, (Label("c".into()), Rotation(2.0), Marker));
+
+        assert_eq!(world.entity(b).archetype(), to);
+        assert_eq!(world.get::<A>(b), Some(&A(1)));
+
+        assert!(world.remove_bundle::<(Label, Velocity)>(a).is_none());
+        assert_eq!(world.entity(a).archetype(), to);
+
+        let tagged = world.remove_bundle::<Tagged>(a).unwrap();
+
+        assert_eq!(tagged.label, Label("b".into()));
+        assert!(!world.entity(a).has_component::<Marker>());
+        assert!(world.entity(b).has_component::<Marker>());
+        assert_eq!(world.get::<Rotation>(a).unwrap().0, 1.0);
+        assert_eq!(world.get::<Label>(b), Some(&Label("c".into())));
+
+        let (rotation, value) = world.remove_bundle::<(Rotation, A)>(a).unwrap();
+
+        assert_eq!((rotation.0, value), (1.0, A(2)));
+        assert!(world
+            .archetypes
+            .get_by_id(world.entity(a).archetype())
+            .types
+            .is_empty());
+        assert!(world.remove_bundle::<(Rotation, A)>(a).is_none());
+    }
+
+    #[test]
+    fn insert_bundle_duplicates() {
+        let mut world = World::new();
+        let a = world.spawn((A(1),));
+
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            world.insert_bundle(a, (Rotation(1.0), A(2), Rotation(2.0)));
+        }))
+        .unwrap_err();
+
+        assert!(payload
+            .downcast_ref::<String>()
+            .unwrap()
+            .contains("appears more than once"));
+        assert_eq!(world.get::<A>(a), Some(&A(1)));
+        assert!(!world.entity(a).has_component::<Rotation>());
+    }
+
+    #[test]
+    fn remove_entity_hook_spawns() {
+        #[derive(Debug)]
+        struct Debris;
+
+        let mut world = World::new();
+
+        world
+            .register_component_hooks::<Marker>()
+            .on_remove(|_, _, commands| {
+                commands.spawn((Debris,));
+            });
+
+        let a = world.spawn((Marker,));
+        let b = world.spawn((Marker,));
+
+        world.remove_entity(a);
+        world.remove_entity(b);
+
+        let mut debris = world
+            .query::<(Entity, &Debris)>()
+            .iter()
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+        debris.sort();
+
+        assert_eq!(debris.len(), 2);
+        debris.dedup();
+        assert_eq!(debris.len(), 2);
+        assert!(debris.iter().all(|&entity| entity != a && entity != b));
+    }
+
+    #[test]
+    #[should_panic(expected = "no entities left")]
+    fn spawn_past_range() {
+        let mut world = World::new();
+        world.entities.set_range(0..2);
+
+        let a = world.spawn((A(1),));
+        let b = world.spawn((A(2),));
+        assert_ne!(a, b);
+
+        world.spawn((A(3),));
+    }
+
+    #[test]
+    fn reserved_entity() {
+        let mut world = World::new();
+
+        let a = world.reserve_entity();
+        world.add_component(a, A(1));
 
-        assert_eq!(world.query::<Label>()[0], &Label("abc".into()));
+        let b = world.reserve_entity();
+        assert!(world.get_mut::<A>(b).is_none());
 
-        world.query_mut::<Label>()[0].0 += "efg";
+        let c = world.reserve_entity();
+        world.insert_bundle(c, (A(3), Marker));
 
-        assert_eq!(world.query::<Label>()[0], &Label("abcefg".into()));
+        assert_eq!(world.get::<A>(a), Some(&A(1)));
+        assert!(world.get_entity(b).is_some());
+        assert_eq!(world.remove_component::<A>(c), Some(A(3)));
     }
 }
@@ -1,46 +1,903 @@
-use std::marker::PhantomData;
+use std::{
+    any::{type_name, TypeId},
+    borrow::Cow,
+    marker::PhantomData,
+    ops::Range,
+    slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use crate::World;
+use crate::{
+    archetype::ArchetypeGeneration, Access, Archetype, ArchetypeId, Column, Component,
+    ComponentSparseSet, ComponentTicks, Entity, Mut, QueryFilter, Tick, World, WorldId,
+};
 
-pub struct Query<'a, T: ComponentsQuery> {
-    world: &'a World,
-    fetch: T::Fetch,
-    _marker: PhantomData<T>,
+/// The reusable part of a query: its access and the archetypes it matches.
+///
+/// Matching archetypes are cached, and only archetypes created since the
+/// state was last updated are checked again. A state can only be used with
+/// the world it was created for.
+pub struct QueryState<T: ComponentsQuery, F: QueryFilter = ()> {
+    world_id: WorldId,
+    archetype_generation: ArchetypeGeneration,
+    archetypes: Vec<ArchetypeId>,
+    access: Access,
+    _marker: PhantomData<fn() -> (T, F)>,
+}
+
+impl<T: ComponentsQuery, F: QueryFilter> QueryState<T, F> {
+    /// Creates the state, panicking if the query's accesses conflict.
+    pub fn new(world: &World) -> Self {
+        let mut state = Self {
+            world_id: world.id(),
+            archetype_generation: ArchetypeGeneration::initial(),
+            archetypes: Vec::new(),
+            access: Self::access_of(),
+            _marker: PhantomData,
+        };
+
+        state.update_archetypes(world);
+        state
+    }
+
+    /// Works out the query's access without a world, panicking if it
+    /// conflicts with itself.
+    pub fn access_of() -> Access {
+        let mut access = Access::new();
+        T::update_access(&mut access);
+
+        // Filters only look at ticks, so they don't conflict with the
+        // components the query itself fetches.
+        let mut filter_access = Access::new();
+        F::update_access(&mut filter_access);
+        access.extend(&filter_access);
+
+        access
+    }
+
+    /// Every component type the query reads or writes, including through its
+    /// filter.
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// The archetypes the query matched the last time it was updated.
+    pub fn matched_archetypes(&self) -> &[ArchetypeId] {
+        &self.archetypes
+    }
+
+    /// Checks the archetypes created since the last update.
+    pub fn update_archetypes(&mut self, world: &World) {
+        self.validate_world(world);
+
+        let generation = world.archetypes.generation();
+
+        for archetype in world.archetypes.since(self.archetype_generation) {
+            if T::matches(world, archetype) && F::matches(world, archetype) {
+                self.archetypes.push(archetype.id());
+            }
+        }
+
+        self.archetype_generation = generation;
+    }
+
+    pub fn query<'w, 's>(&'s mut self, world: &'w World) -> Query<'w, 's, T, F>
+    where
+        T: ReadOnlyQuery,
+    {
+        self.update_archetypes(world);
+
+        unsafe { self.query_unchecked(world, world.last_change_tick(), world.change_tick()) }
+    }
+
+    pub fn query_mut<'w, 's>(&'s mut self, world: &'w mut World) -> Query<'w, 's, T, F> {
+        self.update_archetypes(world);
+
+        let (last_run, this_run) = (world.last_change_tick(), world.change_tick());
+
+        unsafe { self.query_unchecked(world, last_run, this_run) }
+    }
+
+    /// Creates a query from the archetypes matched so far, without updating
+    /// them.
+    ///
+    /// # Safety
+    /// The caller must ensure nothing else accesses the components `T` writes
+    /// for as long as the query lives.
+    pub unsafe fn query_unchecked<'w, 's>(
+        &'s self,
+        world: &'w World,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Query<'w, 's, T, F> {
+        self.validate_world(world);
+
+        Query::from_archetypes(world, Cow::Borrowed(&self.archetypes), last_run, this_run)
+    }
+
+    fn validate_world(&self, world: &World) {
+        assert_eq!(
+            self.world_id,
+            world.id(),
+            "the query state was created for a different world"
+        );
+    }
 }
 
-impl<'a, T: ComponentsQuery> Query<'a, T> {
-    pub fn new(world: &'a World) -> Self {
-        let fetch = T::init(world);
+/// How many rows a parallel query hands to a thread at a time, unless
+/// [`Query::with_batch_size`] says otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
 
+/// A query over every entity whose components match `T` and that pass the
+/// filter `F`.
+pub struct Query<'w, 's, T: ComponentsQuery, F: QueryFilter = ()> {
+    world: &'w World,
+    archetypes: Cow<'s, [ArchetypeId]>,
+    last_run: Tick,
+    this_run: Tick,
+    batch_size: usize,
+    _marker: PhantomData<(T, F)>,
+}
+
+impl<'w, 's, T: ComponentsQuery, F: QueryFilter> Query<'w, 's, T, F> {
+    /// Creates a one-off query that reports changes made after `last_run`.
+    ///
+    /// # Safety
+    /// The caller must ensure nothing else accesses the components `T` writes
+    /// for as long as the query lives.
+    pub(crate) unsafe fn new(world: &'w World, last_run: Tick, this_run: Tick) -> Self {
+        let state = QueryState::<T, F>::new(world);
+
+        Self::from_archetypes(world, Cow::Owned(state.archetypes), last_run, this_run)
+    }
+
+    unsafe fn from_archetypes(
+        world: &'w World,
+        archetypes: Cow<'s, [ArchetypeId]>,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self {
         Self {
             world,
-            fetch,
+            archetypes,
+            last_run,
+            this_run,
+            batch_size: DEFAULT_BATCH_SIZE,
             _marker: PhantomData,
         }
     }
+
+    /// Sets how many rows the `par_for_each` methods hand to a thread at a
+    /// time.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be at least 1");
+
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn iter(&self) -> QueryIter<'_, '_, T, F>
+    where
+        T: ReadOnlyQuery,
+    {
+        unsafe { self.iter_unchecked() }
+    }
+
+    pub fn iter_mut(&mut self) -> QueryIter<'_, '_, T, F> {
+        unsafe { self.iter_unchecked() }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<T::Item<'_>>
+    where
+        T: ReadOnlyQuery,
+    {
+        unsafe { self.get_unchecked(entity) }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<T::Item<'_>> {
+        unsafe { self.get_unchecked(entity) }
+    }
+
+    /// Returns `true` if the query matches no entities.
+    pub fn is_empty(&self) -> bool {
+        unsafe { self.iter_unchecked().next().is_none() }
+    }
+
+    /// Calls `f` on every matching entity, spreading the rows over several
+    /// threads.
+    pub fn par_for_each<'a>(&'a self, f: impl Fn(T::Item<'a>) + Send + Sync)
+    where
+        T: ReadOnlyQuery,
+    {
+        unsafe { self.par_for_each_unchecked(f) }
+    }
+
+    /// Like [`Query::par_for_each`], but with mutable access to the components.
+    pub fn par_for_each_mut<'a>(&'a mut self, f: impl Fn(T::Item<'a>) + Send + Sync) {
+        unsafe { self.par_for_each_unchecked(f) }
+    }
+
+    /// Splits every matched archetype into batches of rows, which the world's
+    /// task pool and the current thread then take from a shared counter until
+    /// none are left. Batches never share rows, so no two threads fetch the
+    /// same component.
+    unsafe fn par_for_each_unchecked<'a>(&'a self, f: impl Fn(T::Item<'a>) + Send + Sync) {
+        let world = self.world;
+        let (last_run, this_run) = (self.last_run, self.this_run);
+
+        let batches = self
+            .archetypes
+            .iter()
+            .flat_map(|&id| {
+                let len = world.archetypes.get_by_id(id).len();
+
+                (0..len)
+                    .step_by(self.batch_size)
+                    .map(move |start| (id, start..(start + self.batch_size).min(len)))
+            })
+            .collect::<Vec<(ArchetypeId, Range<usize>)>>();
+
+        let run = |(id, rows): &(ArchetypeId, Range<usize>)| {
+            let archetype = world.archetypes.get_by_id(*id);
+
+            let filter = F::init(world, archetype, last_run, this_run);
+            let fetch = T::init(world, archetype, last_run, this_run);
+
+            for row in rows.clone() {
+                if F::filter_fetch(&filter, row) && T::filter_fetch(&fetch, row) {
+                    f(T::fetch(&fetch, row));
+                }
+            }
+        };
+
+        if batches.len() <= 1 {
+            batches.iter().for_each(run);
+            return;
+        }
+
+        let next = AtomicUsize::new(0);
+
+        world.task_pool().broadcast(batches.len() - 1, &|| {
+            while let Some(batch) = batches.get(next.fetch_add(1, Ordering::Relaxed)) {
+                run(batch);
+            }
+        });
+    }
+
+    unsafe fn iter_unchecked(&self) -> QueryIter<'_, '_, T, F> {
+        QueryIter::new(
+            self.world,
+            Archetypes::Borrowed(self.archetypes.iter()),
+            self.last_run,
+            self.this_run,
+        )
+    }
+
+    unsafe fn get_unchecked(&self, entity: Entity) -> Option<T::Item<'_>> {
+        let location = self.world.entities.get(entity)?;
+
+        if !self.archetypes.contains(&location.archetype()) {
+            return None;
+        }
+
+        let archetype = self.world.archetypes.get_by_id(location.archetype());
+
+        let filter = F::init(self.world, archetype, self.last_run, self.this_run);
+
+        if !F::filter_fetch(&filter, location.row) {
+            return None;
+        }
+
+        let fetch = T::init(self.world, archetype, self.last_run, self.this_run);
+
+        if !T::filter_fetch(&fetch, location.row) {
+            return None;
+        }
+
+        Some(T::fetch(&fetch, location.row))
+    }
+}
+
+impl<'w, 's, T: ComponentsQuery, F: QueryFilter> IntoIterator for Query<'w, 's, T, F> {
+    type Item = T::Item<'w>;
+    type IntoIter = QueryIter<'w, 's, T, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let archetypes = match self.archetypes {
+            Cow::Borrowed(archetypes) => Archetypes::Borrowed(archetypes.iter()),
+            Cow::Owned(archetypes) => Archetypes::Owned(archetypes.into_iter()),
+        };
+
+        QueryIter::new(self.world, archetypes, self.last_run, self.this_run)
+    }
+}
+
+impl<'q, T: ReadOnlyQuery, F: QueryFilter> IntoIterator for &'q Query<'_, '_, T, F> {
+    type Item = T::Item<'q>;
+    type IntoIter = QueryIter<'q, 'q, T, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'q, T: ComponentsQuery, F: QueryFilter> IntoIterator for &'q mut Query<'_, '_, T, F> {
+    type Item = T::Item<'q>;
+    type IntoIter = QueryIter<'q, 'q, T, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+enum Archetypes<'q> {
+    Borrowed(slice::Iter<'q, ArchetypeId>),
+    Owned(std::vec::IntoIter<ArchetypeId>),
+}
+
+impl Iterator for Archetypes<'_> {
+    type Item = ArchetypeId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Archetypes::Borrowed(iter) => iter.next().copied(),
+            Archetypes::Owned(iter) => iter.next(),
+        }
+    }
+}
+
+/// Iterates the items of a [`Query`], one archetype at a time.
+pub struct QueryIter<'a, 'q, T: ComponentsQuery, F: QueryFilter = ()> {
+    world: &'a World,
+    archetypes: Archetypes<'q>,
+    fetch: Option<(T::Fetch<'a>, F::Fetch<'a>)>,
+    last_run: Tick,
+    this_run: Tick,
+    row: usize,
+    len: usize,
+}
+
+impl<'a, 'q, T: ComponentsQuery, F: QueryFilter> QueryIter<'a, 'q, T, F> {
+    fn new(world: &'a World, archetypes: Archetypes<'q>, last_run: Tick, this_run: Tick) -> Self {
+        Self {
+            world,
+            archetypes,
+            fetch: None,
+            last_run,
+            this_run,
+            row: 0,
+            len: 0,
+        }
+    }
+}
+
+impl<'a, T: ComponentsQuery, F: QueryFilter> Iterator for QueryIter<'a, '_, T, F> {
+    type Item = T::Item<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((fetch, filter)) = &self.fetch {
+                while self.row < self.len {
+                    let row = self.row;
+                    self.row += 1;
+
+                    if unsafe { F::filter_fetch(filter, row) && T::filter_fetch(fetch, row) } {
+                        return Some(unsafe { T::fetch(fetch, row) });
+                    }
+                }
+            }
+
+            let archetype = self.world.archetypes.get_by_id(self.archetypes.next()?);
+
+            self.fetch = Some(unsafe {
+                (
+                    T::init(self.world, archetype, self.last_run, self.this_run),
+                    F::init(self.world, archetype, self.last_run, self.this_run),
+                )
+            });
+            self.row = 0;
+            self.len = archetype.len();
+        }
+    }
+}
+
+/// Describes which components a query fetches and how.
+///
+/// Implemented for `&T`, `&mut T`, [`Entity`] and tuples of up to 12 queries.
+///
+/// # Safety
+/// [`ComponentsQuery::update_access`] must report every component that
+/// [`ComponentsQuery::fetch`] reads or writes.
+pub unsafe trait ComponentsQuery {
+    type Item<'a>;
+    type Fetch<'a>;
+
+    /// Records the components this query accesses, panicking if it conflicts
+    /// with an access that is already recorded.
+    fn update_access(access: &mut Access);
+
+    /// Returns `true` if the query can match entities in `archetype`.
+    /// Components stored in sparse sets match every archetype, and are then
+    /// checked per row by [`ComponentsQuery::filter_fetch`].
+    fn matches(world: &World, archetype: &Archetype) -> bool;
+
+    /// Prepares to fetch from `archetype`. Mutable fetches stamp changes with
+    /// `this_run`.
+    ///
+    /// # Safety
+    /// `archetype` must match the query.
+    unsafe fn init<'a>(
+        world: &'a World,
+        archetype: &'a Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'a>;
+
+    /// Returns `false` if the entity in `row` lacks a component the query
+    /// fetches from a sparse set.
+    ///
+    /// # Safety
+    /// `row` must be in bounds, and the caller must uphold the aliasing rules
+    /// for the components this query writes.
+    unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool;
+
+    /// # Safety
+    /// `row` must be in bounds and pass [`ComponentsQuery::filter_fetch`], and
+    /// the caller must uphold the aliasing rules for the components this query
+    /// writes.
+    unsafe fn fetch<'a>(fetch: &Self::Fetch<'a>, row: usize) -> Self::Item<'a>;
+}
+
+/// Where the values of one component type are for the rows of an archetype.
+pub enum ComponentFetch<'a> {
+    /// The archetype's own column.
+    Table(&'a Column),
+    /// The component's sparse set, looked up by the archetype's entities.
+    SparseSet(&'a [Entity], &'a ComponentSparseSet),
+}
+
+impl<'a> ComponentFetch<'a> {
+    /// Returns `None` if `archetype` has no column for `T` and `T` isn't
+    /// stored in a sparse set.
+    pub(crate) fn new<T: Component>(world: &'a World, archetype: &'a Archetype) -> Option<Self> {
+        match archetype.column::<T>() {
+            Some(column) => Some(Self::Table(column)),
+            None => world
+                .sparse_sets
+                .get(TypeId::of::<T>())
+                .map(|set| Self::SparseSet(archetype.entities(), set)),
+        }
+    }
+
+    /// Finds the column holding the value for `row`, and the value's row in
+    /// it.
+    ///
+    /// # Safety
+    /// `row` must be in bounds of the archetype.
+    unsafe fn locate(&self, row: usize) -> Option<(&'a Column, usize)> {
+        match *self {
+            Self::Table(column) => Some((column, row)),
+            Self::SparseSet(entities, set) => {
+                let index = set.index_of(*entities.get_unchecked(row))?;
+
+                Some((set.column(), index))
+            }
+        }
+    }
+
+    /// # Safety
+    /// `row` must be in bounds of the archetype.
+    pub(crate) unsafe fn contains(&self, row: usize) -> bool {
+        match self {
+            Self::Table(_) => true,
+            Self::SparseSet(..) => self.locate(row).is_some(),
+        }
+    }
+
+    /// # Safety
+    /// `row` must be in bounds of the archetype, and its entity must have the
+    /// component.
+    pub(crate) unsafe fn get(&self, row: usize) -> *mut u8 {
+        let (column, row) = self.locate(row).unwrap_unchecked();
+
+        column.get_unchecked(row)
+    }
+
+    /// # Safety
+    /// `row` must be in bounds of the archetype, and its entity must have the
+    /// component.
+    pub(crate) unsafe fn ticks(&self, row: usize) -> *mut ComponentTicks {
+        let (column, row) = self.locate(row).unwrap_unchecked();
+
+        column.ticks_ptr().as_ptr().add(row)
+    }
+}
+
+/// A [`ComponentsQuery`] that never writes, and so can be run through a
+/// shared borrow.
+///
+/// # Safety
+/// The query must not access any components mutably.
+pub unsafe trait ReadOnlyQuery: ComponentsQuery {}
+
+unsafe impl<T: Component> ComponentsQuery for &T {
+    type Item<'a> = &'a T;
+    type Fetch<'a> = ComponentFetch<'a>;
+
+    fn update_access(access: &mut Access) {
+        let id = TypeId::of::<T>();
+
+        assert!(
+            !access.has_write(id),
+            "&{} conflicts with a mutable access in the same query",
+            type_name::<T>()
+        );
+
+        access.add_read(id);
+    }
+
+    fn matches(world: &World, archetype: &Archetype) -> bool {
+        archetype.has_component::<T>() || world.sparse_sets.contains(TypeId::of::<T>())
+    }
+
+    unsafe fn init<'a>(
+        world: &'a World,
+        archetype: &'a Archetype,
+        _last_run: Tick,
+        _this_run: Tick,
+    ) -> Self::Fetch<'a> {
+        ComponentFetch::new::<T>(world, archetype).unwrap()
+    }
+
+    unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool {
+        fetch.contains(row)
+    }
+
+    unsafe fn fetch<'a>(fetch: &Self::Fetch<'a>, row: usize) -> Self::Item<'a> {
+        &*(fetch.get(row) as *const T)
+    }
+}
+
+unsafe impl<T: Component> ReadOnlyQuery for &T {}
+
+unsafe impl<T: Component> ComponentsQuery for &mut T {
+    type Item<'a> = Mut<'a, T>;
+    type Fetch<'a> = (ComponentFetch<'a>, Tick);
+
+    fn update_access(access: &mut Access) {
+        let id = TypeId::of::<T>();
+
+        assert!(
+            !access.has_read(id) && !access.has_write(id),
+            "&mut {} conflicts with another access in the same query",
+            type_name::<T>()
+        );
+
+        access.add_write(id);
+    }
+
+    fn matches(world: &World, archetype: &Archetype) -> bool {
+        archetype.has_component::<T>() || world.sparse_sets.contains(TypeId::of::<T>())
+    }
+
+    unsafe fn init<'a>(
+        world: &'a World,
+        archetype: &'a Archetype,
+        _last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'a> {
+        (
+            ComponentFetch::new::<T>(world, archetype).unwrap(),
+            this_run,
+        )
+    }
+
+    unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool {
+        fetch.0.contains(row)
+    }
+
+    unsafe fn fetch<'a>(fetch: &Self::Fetch<'a>, row: usize) -> Self::Item<'a> {
+        let (values, this_run) = fetch;
+
+        Mut::new(
+            &mut *(values.get(row) as *mut T),
+            &mut (*values.ticks(row)).changed,
+            *this_run,
+        )
+    }
+}
+
+unsafe impl ComponentsQuery for Entity {
+    type Item<'a> = Entity;
+    type Fetch<'a> = &'a [Entity];
+
+    fn update_access(_access: &mut Access) {}
+
+    fn matches(_world: &World, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    unsafe fn init<'a>(
+        _world: &'a World,
+        archetype: &'a Archetype,
+        _last_run: Tick,
+        _this_run: Tick,
+    ) -> Self::Fetch<'a> {
+        archetype.entities()
+    }
+
+    unsafe fn filter_fetch(_fetch: &Self::Fetch<'_>, _row: usize) -> bool {
+        true
+    }
+
+    unsafe fn fetch<'a>(fetch: &Self::Fetch<'a>, row: usize) -> Self::Item<'a> {
+        *fetch.get_unchecked(row)
+    }
 }
 
-pub trait ComponentsQuery {
-    type Fetch;
+unsafe impl ReadOnlyQuery for Entity {}
 
-    fn init(world: &World) -> Self::Fetch;
+macro_rules! impl_query {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: ComponentsQuery),*> ComponentsQuery for ($($name,)*) {
+            type Item<'a> = ($($name::Item<'a>,)*);
+            type Fetch<'a> = ($($name::Fetch<'a>,)*);
+
+            fn update_access(access: &mut Access) {
+                $($name::update_access(access);)*
+            }
+
+            fn matches(world: &World, archetype: &Archetype) -> bool {
+                true $(&& $name::matches(world, archetype))*
+            }
+
+            unsafe fn init<'a>(world: &'a World, archetype: &'a Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'a> {
+                ($($name::init(world, archetype, last_run, this_run),)*)
+            }
+
+            unsafe fn filter_fetch(fetch: &Self::Fetch<'_>, row: usize) -> bool {
+                let ($($name,)*) = fetch;
+                true $(&& $name::filter_fetch($name, row))*
+            }
+
+            unsafe fn fetch<'a>(fetch: &Self::Fetch<'a>, row: usize) -> Self::Item<'a> {
+                let ($($name,)*) = fetch;
+                ($($name::fetch($name, row),)*)
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlyQuery),*> ReadOnlyQuery for ($($name,)*) {}
+    };
 }
 
-/*
-impl<T> ComponentsQuery for T {
-    type Fetch = Vec<T>;
+impl_query!();
+impl_query!(A);
+impl_query!(A, B);
+impl_query!(A, B, C);
+impl_query!(A, B, C, D);
+impl_query!(A, B, C, D, E);
+impl_query!(A, B, C, D, E, F);
+impl_query!(A, B, C, D, E, F, G);
+impl_query!(A, B, C, D, E, F, G, H);
+impl_query!(A, B, C, D, E, F, G, H, I);
+impl_query!(A, B, C, D, E, F, G, H, I, J);
+impl_query!(A, B, C, D, E, F, G, H, I, J, K);
+impl_query!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Entity, World};
 
-    fn init(world: &World) -> Self::Fetch {
-        //world.archetypes.
+    #[derive(Debug, PartialEq)]
+    struct Position(f32, f32);
 
+    #[derive(Debug, PartialEq)]
+    struct Velocity(f32, f32);
+
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+
+    #[test]
+    fn test_tuple() {
+        let mut world = World::new();
+
+        world.spawn((Position(0.0, 0.0), Velocity(1.0, 2.0)));
+        world.spawn((Position(5.0, 5.0), Velocity(-1.0, 0.0), Name("b")));
+        world.spawn((Position(9.0, 9.0),));
+
+        for (mut position, velocity) in world.query_mut::<(&mut Position, &Velocity)>() {
+            position.0 += velocity.0;
+            position.1 += velocity.1;
+        }
+
+        let mut positions = world
+            .query::<&Position>()
+            .into_iter()
+            .map(|p| (p.0, p.1))
+            .collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        assert_eq!(positions, [(1.0, 2.0), (4.0, 5.0), (9.0, 9.0)]);
+
+        let query = world.query::<(&Name, &Position, &Velocity)>();
+        let items = query.iter().collect::<Vec<_>>();
+
+        assert_eq!(
+            items,
+            [(&Name("b"), &Position(4.0, 5.0), &Velocity(-1.0, 0.0))]
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        let mut world = World::new();
+
+        let a = world.spawn((Position(0.0, 0.0), Velocity(1.0, 2.0)));
+        let b = world.spawn((Position(5.0, 5.0),));
+
+        let mut query = world.query_mut::<(&mut Position, &Velocity)>();
+
+        assert!(query.get_mut(b).is_none());
+
+        let (mut position, velocity) = query.get_mut(a).unwrap();
+        position.0 += velocity.0;
+
+        assert_eq!(world.get::<Position>(a), Some(&Position(1.0, 0.0)));
+    }
+
+    #[test]
+    fn test_entity() {
+        let mut world = World::new();
+
+        let a = world.spawn((Position(0.0, 0.0), Name("a")));
+        let b = world.spawn((Position(1.0, 0.0),));
+        let c = world.spawn((Position(2.0, 0.0), Name("c")));
+
+        let dead = world
+            .query::<(Entity, &Position)>()
+            .into_iter()
+            .filter(|(_, position)| position.0 < 1.5)
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        assert_eq!(dead, [a, b]);
+
+        for entity in dead {
+            world.remove_entity(entity);
+        }
+
+        let query = world.query::<(Entity, &Name)>();
+
+        assert_eq!(query.iter().collect::<Vec<_>>(), [(c, &Name("c"))]);
+    }
+
+    #[test]
+    fn test_state() {
+        let mut world = World::new();
+
+        world.spawn((Position(0.0, 0.0), Velocity(1.0, 0.0)));
+        world.spawn((Position(1.0, 0.0),));
+
+        let mut state = QueryState::<(&mut Position, &Velocity)>::new(&world);
+
+        assert_eq!(state.matched_archetypes().len(), 1);
+
+        for (mut position, velocity) in state.query_mut(&mut world) {
+            position.0 += velocity.0;
+        }
+
+        world.spawn((Position(2.0, 0.0), Velocity(2.0, 0.0), Name("c")));
+        world.spawn((Name("d"),));
+
+        for (mut position, velocity) in state.query_mut(&mut world) {
+            position.0 += velocity.0;
+        }
+
+        assert_eq!(state.matched_archetypes().len(), 2);
+
+        let mut state = QueryState::<&Position>::new(&world);
+        let positions = state
+            .query(&world)
+            .into_iter()
+            .map(|p| p.0)
+            .collect::<Vec<_>>();
+
+        assert_eq!(positions, [2.0, 1.0, 4.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "different world")]
+    fn test_state_other_world() {
+        let world = World::new();
+        let mut other = World::new();
+        other.spawn((Position(0.0, 0.0),));
+
+        let mut state = QueryState::<&Position>::new(&world);
+
+        state.query(&other);
+    }
+
+    #[test]
+    fn test_par_for_each() {
+        let mut world = World::new();
+
+        for i in 0..100 {
+            world.spawn((Position(i as f32, 0.0), Velocity(1.0, 0.0)));
+            world.spawn((Position(i as f32, 0.0), Velocity(2.0, 0.0), Name("b")));
+            world.spawn((Position(i as f32, 0.0),));
+        }
+
+        world
+            .query_mut::<(&mut Position, &Velocity)>()
+            .with_batch_size(7)
+            .par_for_each_mut(|(mut position, velocity)| position.0 += velocity.0);
+
+        let sum = AtomicUsize::new(0);
+
+        world
+            .query::<&Position>()
+            .with_batch_size(16)
+            .par_for_each(|position| {
+                sum.fetch_add(position.0 as usize, Ordering::Relaxed);
+            });
+
+        // Three copies of 0..100, shifted by 1 and 2 for the ones with a
+        // velocity.
+        assert_eq!(sum.into_inner(), 3 * 4950 + 100 + 200);
+    }
+
+    #[test]
+    fn test_par_for_each_visits_once() {
+        #[derive(Debug)]
+        struct Visits(AtomicUsize);
+
+        let mut world = World::new();
+
+        for i in 0..1000 {
+            let visits = Visits(AtomicUsize::new(0));
+
+            match i % 3 {
+                0 => world.spawn((visits,)),
+                1 => world.spawn((visits, Name("a"))),
+                _ => world.spawn((visits, Position(0.0, 0.0))),
+            };
+        }
+
+        // The pool threads are reused from one call to the next.
+        for _ in 0..5 {
+            world
+                .query::<&Visits>()
+                .with_batch_size(7)
+                .par_for_each(|visits| {
+                    visits.0.fetch_add(1, Ordering::Relaxed);
+                });
+        }
+
+        let visits = world
+            .query::<&Visits>()
+            .iter()
+            .map(|visits| visits.0.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+
+        assert_eq!(visits.len(), 1000);
+        assert!(visits.iter().all(|&visits| visits == 5));
     }
-}*/
 
-/*
-impl<T: ComponentsQuery> Query<T> for T {
-    fn new() -> Self {
-        todo!()
+    #[test]
+    #[should_panic]
+    fn test_conflict() {
+        let mut world = World::new();
+
+        world.query_mut::<(&mut Position, &Position)>();
     }
-}*/
 
-pub trait State: Sized {}
+    #[test]
+    #[should_panic]
+    fn test_conflict_mut() {
+        let mut world = World::new();
+
+        world.query_mut::<(&Velocity, (&mut Position, &mut Position))>();
+    }
+}
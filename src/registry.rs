@@ -0,0 +1,344 @@
+use std::{alloc::Layout, any::TypeId, fmt, ptr};
+
+use rustc_hash::FxHashMap;
+
+use crate::{Component, SnapshotComponent, StorageType, TypeInfo, Value};
+
+/// Identifies a component type within one [`World`](crate::World). Ids are
+/// handed out in the order types are registered and never change or get
+/// reused.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ComponentId(usize);
+
+impl ComponentId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Writes a clone of the value behind the first pointer to the second.
+pub type CloneFn = unsafe fn(*const u8, *mut u8);
+
+/// Writes a default value to the pointer.
+pub type DefaultFn = unsafe fn(*mut u8);
+
+/// Formats the value behind the pointer.
+pub type DebugFn = unsafe fn(*const u8, &mut fmt::Formatter<'_>) -> fmt::Result;
+
+/// Converts the value behind the pointer for a snapshot.
+pub type ToValueFn = unsafe fn(*const u8) -> Value;
+
+/// Writes the value read from a snapshot to the pointer, returning `false`
+/// without writing anything if the value is invalid.
+pub type FromValueFn = unsafe fn(&Value, *mut u8) -> bool;
+
+/// Everything the world knows about a component type, for code that has to
+/// handle components without knowing their types.
+#[derive(Clone, Copy)]
+pub struct ComponentDescriptor {
+    id: ComponentId,
+    info: TypeInfo,
+    storage: StorageType,
+    clone: Option<CloneFn>,
+    debug: Option<DebugFn>,
+    default: Option<DefaultFn>,
+    to_value: Option<ToValueFn>,
+    from_value: Option<FromValueFn>,
+    pod: bool,
+}
+
+impl ComponentDescriptor {
+    fn new(id: ComponentId, info: TypeInfo) -> Self {
+        Self {
+            id,
+            info,
+            storage: StorageType::Table,
+            clone: None,
+            debug: Some(info.debug_fn()),
+            default: None,
+            to_value: None,
+            from_value: None,
+            pod: false,
+        }
+    }
+
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        self.info.id()
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.info.type_name()
+    }
+
+    pub fn info(&self) -> &TypeInfo {
+        &self.info
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.info.layout()
+    }
+
+    pub fn size(&self) -> usize {
+        self.info.layout().size()
+    }
+
+    pub fn align(&self) -> usize {
+        self.info.layout().align()
+    }
+
+    pub fn storage_type(&self) -> StorageType {
+        self.storage
+    }
+
+    pub fn drop_fn(&self) -> unsafe fn(*mut u8) {
+        self.info.drop_fn()
+    }
+
+    /// Set for types registered with [`ComponentRegistry::register_clone`].
+    pub fn clone_fn(&self) -> Option<CloneFn> {
+        self.clone
+    }
+
+    /// Set for every [`Component`], since they all implement `Debug`.
+    pub fn debug_fn(&self) -> Option<DebugFn> {
+        self.debug
+    }
+
+    /// Set for types registered with [`ComponentRegistry::register_default`].
+    pub fn default_fn(&self) -> Option<DefaultFn> {
+        self.default
+    }
+
+    /// Whether the type was registered with [`ComponentRegistry::register_pod`],
+    /// so its values can be copied as plain bytes.
+    pub fn is_pod(&self) -> bool {
+        self.pod
+    }
+
+    /// Set for types registered with [`ComponentRegistry::register_snapshot`].
+    pub fn to_value_fn(&self) -> Option<ToValueFn> {
+        self.to_value
+    }
+
+    /// Set for types registered with [`ComponentRegistry::register_snapshot`].
+    pub fn from_value_fn(&self) -> Option<FromValueFn> {
+        self.from_value
+    }
+}
+
+impl fmt::Debug for ComponentDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentDescriptor")
+            .field("id", &self.id)
+            .field("type_name", &self.type_name())
+            .field("size", &self.size())
+            .field("align", &self.align())
+            .field("storage", &self.storage)
+            .field("clone", &self.clone.is_some())
+            .field("default", &self.default.is_some())
+            .field("snapshot", &self.to_value.is_some())
+            .field("pod", &self.pod)
+            .finish()
+    }
+}
+
+/// Every component type a world has seen, indexed by [`ComponentId`].
+///
+/// Types are registered the first time they are stored in the world, and can
+/// be registered ahead of that to record how they are cloned, defaulted or
+/// written to snapshots.
+#[derive(Default, Clone)]
+pub struct ComponentRegistry {
+    components: Vec<ComponentDescriptor>,
+    ids: FxHashMap<TypeId, ComponentId>,
+}
+
+impl fmt::Debug for ComponentRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.components).finish()
+    }
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `T` if it isn't registered yet, returning its id.
+    pub fn register<T: Component>(&mut self) -> ComponentId {
+        self.register_info(&TypeInfo::of::<T>())
+    }
+
+    pub(crate) fn register_info(&mut self, info: &TypeInfo) -> ComponentId {
+        if let Some(&id) = self.ids.get(&info.id()) {
+            return id;
+        }
+
+        let id = ComponentId(self.components.len());
+
+        self.components.push(ComponentDescriptor::new(id, *info));
+        self.ids.insert(info.id(), id);
+
+        id
+    }
+
+    /// Registers `T` along with a function that clones it.
+    pub fn register_clone<T: Component + Clone>(&mut self) -> ComponentId {
+        unsafe fn clone_ptr<T: Clone>(src: *const u8, dst: *mut u8) {
+            ptr::write(dst as *mut T, (*(src as *const T)).clone())
+        }
+
+        let id = self.register::<T>();
+        self.components[id.0].clone = Some(clone_ptr::<T>);
+
+        id
+    }
+
+    /// Registers `T` along with a function that creates its default value.
+    pub fn register_default<T: Component + Default>(&mut self) -> ComponentId {
+        unsafe fn default_ptr<T: Default>(dst: *mut u8) {
+            ptr::write(dst as *mut T, T::default())
+        }
+
+        let id = self.register::<T>();
+        self.components[id.0].default = Some(default_ptr::<T>);
+
+        id
+    }
+
+    /// Registers `T` as plain data that can be copied byte for byte, which
+    /// [`WorldSnapshot`](crate::WorldSnapshot) relies on.
+    pub fn register_pod<T: Component + Copy>(&mut self) -> ComponentId {
+        let id = self.register::<T>();
+        self.components[id.0].pod = true;
+
+        id
+    }
+
+    /// Registers `T` along with functions that write it to snapshots and read
+    /// it back.
+    pub fn register_snapshot<T: SnapshotComponent>(&mut self) -> ComponentId {
+        unsafe fn to_value<T: SnapshotComponent>(src: *const u8) -> Value {
+            (*(src as *const T)).to_value()
+        }
+
+        unsafe fn from_value<T: SnapshotComponent>(value: &Value, dst: *mut u8) -> bool {
+            T::from_value(value)
+                .map(|c| ptr::write(dst as *mut T, c))
+                .is_some()
+        }
+
+        let id = self.register::<T>();
+        self.components[id.0].to_value = Some(to_value::<T>);
+        self.components[id.0].from_value = Some(from_value::<T>);
+
+        id
+    }
+
+    pub(crate) fn set_storage_type(&mut self, id: ComponentId, storage: StorageType) {
+        self.components[id.0].storage = storage;
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    pub fn get(&self, id: ComponentId) -> Option<&ComponentDescriptor> {
+        self.components.get(id.0)
+    }
+
+    pub fn get_id(&self, type_id: TypeId) -> Option<ComponentId> {
+        self.ids.get(&type_id).copied()
+    }
+
+    pub fn component_id<T: Component>(&self) -> Option<ComponentId> {
+        self.get_id(TypeId::of::<T>())
+    }
+
+    pub fn get_by_type_id(&self, type_id: TypeId) -> Option<&ComponentDescriptor> {
+        self.get(self.get_id(type_id)?)
+    }
+
+    /// Looks a type up by its [`type_name`](std::any::type_name).
+    pub fn get_by_name(&self, name: &str) -> Option<&ComponentDescriptor> {
+        self.components
+            .iter()
+            .find(|descriptor| descriptor.type_name() == name)
+    }
+
+    /// Iterates the registered types in the order of their ids.
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentDescriptor> {
+        self.components.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::type_name;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    struct Name(String);
+
+    #[derive(Debug)]
+    struct Marker;
+
+    #[test]
+    fn test_register() {
+        let mut registry = ComponentRegistry::new();
+
+        let name = registry.register::<Name>();
+        let marker = registry.register::<Marker>();
+
+        assert_eq!(registry.register::<Name>(), name);
+        assert_eq!(registry.component_id::<Marker>(), Some(marker));
+        assert_eq!(registry.len(), 2);
+
+        let descriptor = registry.get(name).unwrap();
+
+        assert_eq!(descriptor.type_name(), type_name::<Name>());
+        assert_eq!(descriptor.size(), std::mem::size_of::<Name>());
+        assert_eq!(descriptor.align(), std::mem::align_of::<Name>());
+        assert!(descriptor.clone_fn().is_none());
+        assert!(descriptor.debug_fn().is_some());
+
+        assert_eq!(
+            registry.get_by_name(type_name::<Marker>()).unwrap().id(),
+            marker
+        );
+    }
+
+    #[test]
+    fn test_clone_default() {
+        let mut registry = ComponentRegistry::new();
+
+        let id = registry.register_clone::<Name>();
+        registry.register_default::<Name>();
+
+        let descriptor = registry.get(id).unwrap();
+
+        let name = Name("a".into());
+        let mut cloned = std::mem::MaybeUninit::<Name>::uninit();
+        let mut default = std::mem::MaybeUninit::<Name>::uninit();
+
+        unsafe {
+            descriptor.clone_fn().unwrap()(
+                &name as *const Name as *const u8,
+                cloned.as_mut_ptr() as *mut u8,
+            );
+            descriptor.default_fn().unwrap()(default.as_mut_ptr() as *mut u8);
+
+            assert_eq!(cloned.assume_init(), name);
+            assert_eq!(default.assume_init(), Name::default());
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    any::{Any, TypeId},
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{ComponentTicks, Mut, Tick};
+
+/// A value that exists once per [`World`](crate::World) instead of once per
+/// entity, like the frame time or input state.
+pub trait Resource: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Resource for T {}
+
+struct ResourceData {
+    value: UnsafeCell<Box<dyn Any + Send + Sync>>,
+    ticks: UnsafeCell<ComponentTicks>,
+}
+
+/// Every resource in a world, keyed by type.
+///
+/// Values sit behind `UnsafeCell`s so that systems can borrow different
+/// resources mutably through a shared world at the same time.
+#[derive(Default)]
+pub struct Resources {
+    resources: FxHashMap<TypeId, ResourceData>,
+}
+
+// Resources are required to be `Send + Sync`, and shared access to the cells
+// is only handed out to systems whose accesses don't conflict.
+unsafe impl Sync for Resources {}
+
+impl Resources {
+    /// Inserts `value`, returning the resource of the same type it replaces.
+    pub fn insert<R: Resource>(&mut self, value: R, tick: Tick) -> Option<R> {
+        let data = ResourceData {
+            value: UnsafeCell::new(Box::new(value)),
+            ticks: UnsafeCell::new(ComponentTicks::new(tick)),
+        };
+
+        self.resources
+            .insert(TypeId::of::<R>(), data)
+            .map(|old| *old.value.into_inner().downcast::<R>().unwrap())
+    }
+
+    pub fn remove<R: Resource>(&mut self) -> Option<R> {
+        let data = self.resources.remove(&TypeId::of::<R>())?;
+
+        Some(*data.value.into_inner().downcast::<R>().unwrap())
+    }
+
+    pub fn contains<R: Resource>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+
+    pub fn get<R: Resource>(&self) -> Option<&R> {
+        unsafe { self.get_unchecked().map(|(value, _)| &*value) }
+    }
+
+    pub fn get_ticks<R: Resource>(&self) -> Option<ComponentTicks> {
+        unsafe { self.get_unchecked::<R>().map(|(_, ticks)| *ticks) }
+    }
+
+    pub fn get_mut<R: Resource>(&mut self, this_run: Tick) -> Option<Mut<'_, R>> {
+        let (value, ticks) = unsafe { self.get_unchecked::<R>()? };
+
+        Some(unsafe { Mut::new(&mut *value, &mut (*ticks).changed, this_run) })
+    }
+
+    /// Returns pointers to the resource and its ticks through a shared
+    /// reference, without creating a reference to either.
+    ///
+    /// # Safety
+    /// The caller may only turn the pointers into references that don't alias
+    /// a `&mut` to the same resource made anywhere else.
+    pub(crate) unsafe fn get_unchecked<R: Resource>(
+        &self,
+    ) -> Option<(*mut R, *mut ComponentTicks)> {
+        let data = self.resources.get(&TypeId::of::<R>())?;
+
+        // Resources are keyed by their type, so the box holds an `R`.
+        let boxed = data.value.get();
+        let value = &raw mut **boxed as *mut R;
+
+        Some((value, data.ticks.get()))
+    }
+}
+
+impl fmt::Debug for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resources")
+            .field("len", &self.resources.len())
+            .finish()
+    }
+}
+
+/// Shared access to the resource `T` in a system.
+pub struct Res<'w, T> {
+    value: &'w T,
+    ticks: ComponentTicks,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<'w, T> Res<'w, T> {
+    pub(crate) fn new(value: &'w T, ticks: ComponentTicks, last_run: Tick, this_run: Tick) -> Self {
+        Self {
+            value,
+            ticks,
+            last_run,
+            this_run,
+        }
+    }
+
+    /// Returns `true` if the resource was inserted since the system last ran.
+    pub fn is_added(&self) -> bool {
+        self.ticks.added.is_newer_than(self.last_run, self.this_run)
+    }
+
+    /// Returns `true` if the resource was inserted or mutably dereferenced
+    /// since the system last ran.
+    pub fn is_changed(&self) -> bool {
+        self.ticks
+            .changed
+            .is_newer_than(self.last_run, self.this_run)
+    }
+
+    pub fn into_inner(self) -> &'w T {
+        self.value
+    }
+}
+
+impl<T> Deref for Res<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Res<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// Mutable access to the resource `T` in a system. Mutably dereferencing it
+/// marks the resource as changed.
+pub struct ResMut<'w, T> {
+    value: &'w mut T,
+    ticks: &'w mut ComponentTicks,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<'w, T> ResMut<'w, T> {
+    pub(crate) fn new(
+        value: &'w mut T,
+        ticks: &'w mut ComponentTicks,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self {
+        Self {
+            value,
+            ticks,
+            last_run,
+            this_run,
+        }
+    }
+
+    /// Returns `true` if the resource was inserted since the system last ran.
+    pub fn is_added(&self) -> bool {
+        self.ticks.added.is_newer_than(self.last_run, self.this_run)
+    }
+
+    /// Returns `true` if the resource was inserted or mutably dereferenced
+    /// since the system last ran.
+    pub fn is_changed(&self) -> bool {
+        self.ticks
+            .changed
+            .is_newer_than(self.last_run, self.this_run)
+    }
+
+    /// Marks the resource as changed without touching it.
+    pub fn set_changed(&mut self) {
+        self.ticks.changed = self.this_run;
+    }
+
+    /// Returns the inner reference without marking the resource as changed.
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.value
+    }
+
+    /// Marks the resource as changed and returns the inner reference.
+    pub fn into_inner(self) -> &'w mut T {
+        self.ticks.changed = self.this_run;
+        self.value
+    }
+}
+
+impl<T> Deref for ResMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T> DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.set_changed();
+        self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ResMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_remove() {
+        let mut resources = Resources::default();
+
+        assert_eq!(resources.insert(1u32, Tick::new(0)), None);
+        assert_eq!(resources.insert(2u32, Tick::new(0)), Some(1));
+        resources.insert("name", Tick::new(0));
+
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources.get::<u32>(), Some(&2));
+
+        assert_eq!(resources.remove::<u32>(), Some(2));
+        assert!(!resources.contains::<u32>());
+        assert!(resources.contains::<&str>());
+    }
+
+    #[test]
+    fn test_ticks() {
+        let mut resources = Resources::default();
+
+        resources.insert(1u32, Tick::new(1));
+
+        *resources.get_mut::<u32>(Tick::new(4)).unwrap() += 1;
+
+        let ticks = resources.get_ticks::<u32>().unwrap();
+
+        assert_eq!(ticks.added, Tick::new(1));
+        assert_eq!(ticks.changed, Tick::new(4));
+    }
+}
@@ -0,0 +1,503 @@
+use std::{any::type_name, borrow::Cow, error::Error, fmt};
+
+use rustc_hash::FxHashMap;
+
+use crate::{executor, IntoSystem, IsFunctionSystem, System, SystemParamFunction, World};
+
+/// Names a system or a set of systems in ordering constraints.
+///
+/// Function systems are labelled with the function's type name, so passing
+/// the function itself refers to it. Strings name sets.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Label(Cow<'static, str>);
+
+impl Label {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+pub trait IntoLabel<Marker> {
+    fn into_label(self) -> Label;
+}
+
+impl IntoLabel<()> for &'static str {
+    fn into_label(self) -> Label {
+        Label(self.into())
+    }
+}
+
+impl IntoLabel<()> for Label {
+    fn into_label(self) -> Label {
+        self
+    }
+}
+
+impl<Marker, Func> IntoLabel<(IsFunctionSystem, Marker)> for Func
+where
+    Func: SystemParamFunction<Marker>,
+{
+    fn into_label(self) -> Label {
+        Label(type_name::<Func>().into())
+    }
+}
+
+/// A system along with the sets it belongs to and where it has to run.
+pub struct SystemConfig {
+    system: Box<dyn System>,
+    sets: Vec<Label>,
+    before: Vec<Label>,
+    after: Vec<Label>,
+}
+
+/// Converts something into a [`SystemConfig`], and adds ordering constraints
+/// to it.
+pub trait IntoSystemConfig<Marker>: Sized {
+    fn into_config(self) -> SystemConfig;
+
+    /// Runs the system before the system or every system in the set `label`.
+    fn before<M>(self, label: impl IntoLabel<M>) -> SystemConfig {
+        let mut config = self.into_config();
+        config.before.push(label.into_label());
+        config
+    }
+
+    /// Runs the system after the system or every system in the set `label`.
+    fn after<M>(self, label: impl IntoLabel<M>) -> SystemConfig {
+        let mut config = self.into_config();
+        config.after.push(label.into_label());
+        config
+    }
+
+    /// Adds the system to the set named `set`.
+    fn in_set(self, set: &'static str) -> SystemConfig {
+        let mut config = self.into_config();
+        config.sets.push(set.into_label());
+        config
+    }
+}
+
+impl IntoSystemConfig<()> for SystemConfig {
+    fn into_config(self) -> SystemConfig {
+        self
+    }
+}
+
+impl<Marker, S: IntoSystem<Marker>> IntoSystemConfig<Marker> for S {
+    fn into_config(self) -> SystemConfig {
+        SystemConfig {
+            system: Box::new(self.into_system()),
+            sets: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+}
+
+/// A named group of systems, ordered against other systems or sets as a
+/// whole.
+#[derive(Debug, Clone)]
+pub struct SystemSet {
+    label: Label,
+    before: Vec<Label>,
+    after: Vec<Label>,
+}
+
+impl SystemSet {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            label: name.into_label(),
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+
+    /// Runs every system in the set before the system or set `label`.
+    pub fn before<M>(mut self, label: impl IntoLabel<M>) -> Self {
+        self.before.push(label.into_label());
+        self
+    }
+
+    /// Runs every system in the set after the system or set `label`.
+    pub fn after<M>(mut self, label: impl IntoLabel<M>) -> Self {
+        self.after.push(label.into_label());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// The systems, in order, each have to run before the next, and the last
+    /// before the first.
+    Cycle(Vec<String>),
+    /// An ordering constraint names something that is neither a system nor a
+    /// set in the schedule.
+    UnknownLabel(Label),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Cycle(systems) => {
+                write!(f, "systems depend on each other in a cycle: ")?;
+
+                for system in systems {
+                    write!(f, "`{}` -> ", system)?;
+                }
+
+                write!(f, "`{}`", systems[0])
+            }
+            ScheduleError::UnknownLabel(label) => {
+                write!(f, "`{}` is neither a system nor a set", label)
+            }
+        }
+    }
+}
+
+impl Error for ScheduleError {}
+
+/// How a [`Schedule`] runs its systems.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutorKind {
+    /// Runs one system at a time, always in the same order.
+    SingleThreaded,
+    /// Runs systems whose accesses don't conflict at the same time, on the
+    /// world's thread pool.
+    #[default]
+    MultiThreaded,
+}
+
+/// Systems and the order to run them in.
+///
+/// The order is worked out the first time the schedule runs after systems or
+/// sets were added. Systems that aren't ordered against each other run in the
+/// order they were added, or at the same time if the executor is
+/// multithreaded and their accesses don't conflict.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<SystemConfig>,
+    sets: Vec<SystemSet>,
+    executor: ExecutorKind,
+    graph: Option<SystemGraph>,
+}
+
+/// The systems sorted topologically, and what each of them waits on.
+struct SystemGraph {
+    order: Vec<usize>,
+    dependencies: Vec<Vec<usize>>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_system<M>(&mut self, system: impl IntoSystemConfig<M>) -> &mut Self {
+        self.systems.push(system.into_config());
+        self.graph = None;
+        self
+    }
+
+    pub fn add_set(&mut self, set: SystemSet) -> &mut Self {
+        self.sets.push(set);
+        self.graph = None;
+        self
+    }
+
+    pub fn executor_kind(&self) -> ExecutorKind {
+        self.executor
+    }
+
+    pub fn set_executor_kind(&mut self, executor: ExecutorKind) -> &mut Self {
+        self.executor = executor;
+        self
+    }
+
+    /// The systems in the order they run in, or an empty iterator if the
+    /// order hasn't been worked out yet.
+    pub fn systems(&self) -> impl Iterator<Item = &dyn System> {
+        self.graph
+            .iter()
+            .flat_map(|graph| &graph.order)
+            .map(|&index| &*self.systems[index].system)
+    }
+
+    /// Works out the order to run the systems in, if it isn't known yet.
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        if self.graph.is_none() {
+            let dependencies = self.dependencies()?;
+            let order = self.sort(&dependencies)?;
+
+            self.graph = Some(SystemGraph {
+                order,
+                dependencies,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Runs every system once, keeping to the ordering constraints.
+    pub fn run(&mut self, world: &mut World) -> Result<(), ScheduleError> {
+        self.build()?;
+
+        let graph = self.graph.as_ref().unwrap();
+
+        let systems = self
+            .systems
+            .iter_mut()
+            .map(|config| &mut *config.system)
+            .collect();
+
+        match self.executor {
+            ExecutorKind::SingleThreaded => {
+                executor::run_single_threaded(systems, &graph.order, world)
+            }
+            ExecutorKind::MultiThreaded => {
+                executor::run_multi_threaded(systems, &graph.order, &graph.dependencies, world)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns, for every system, the systems that have to run before it.
+    fn dependencies(&self) -> Result<Vec<Vec<usize>>, ScheduleError> {
+        let mut labels = FxHashMap::<&Label, Vec<usize>>::default();
+
+        for set in &self.sets {
+            labels.entry(&set.label).or_default();
+        }
+
+        let system_labels = self
+            .systems
+            .iter()
+            .map(|config| Label(config.system.name().to_owned().into()))
+            .collect::<Vec<_>>();
+
+        for (index, config) in self.systems.iter().enumerate() {
+            labels.entry(&system_labels[index]).or_default().push(index);
+
+            for set in &config.sets {
+                labels.entry(set).or_default().push(index);
+            }
+        }
+
+        let resolve = |label: &Label| {
+            labels
+                .get(label)
+                .ok_or_else(|| ScheduleError::UnknownLabel(label.clone()))
+        };
+
+        let mut dependencies = vec![Vec::new(); self.systems.len()];
+
+        for (index, config) in self.systems.iter().enumerate() {
+            for label in &config.before {
+                for &other in resolve(label)? {
+                    dependencies[other].push(index);
+                }
+            }
+
+            for label in &config.after {
+                dependencies[index].extend(resolve(label)?);
+            }
+        }
+
+        for set in &self.sets {
+            let members = resolve(&set.label)?;
+
+            for label in &set.before {
+                for &other in resolve(label)? {
+                    dependencies[other].extend(members);
+                }
+            }
+
+            for label in &set.after {
+                for &member in members {
+                    dependencies[member].extend(resolve(label)?);
+                }
+            }
+        }
+
+        Ok(dependencies)
+    }
+
+    /// Sorts the systems topologically, always picking the earliest added
+    /// system that is ready to run.
+    fn sort(&self, dependencies: &[Vec<usize>]) -> Result<Vec<usize>, ScheduleError> {
+        let mut remaining = dependencies
+            .iter()
+            .map(|dependencies| dependencies.len())
+            .collect::<Vec<_>>();
+
+        let mut dependents = vec![Vec::new(); dependencies.len()];
+
+        for (index, dependencies) in dependencies.iter().enumerate() {
+            for &dependency in dependencies {
+                dependents[dependency].push(index);
+            }
+        }
+
+        let mut order = Vec::with_capacity(dependencies.len());
+        let mut done = vec![false; dependencies.len()];
+
+        while order.len() < dependencies.len() {
+            let Some(next) = (0..remaining.len()).find(|&i| !done[i] && remaining[i] == 0) else {
+                return Err(self.find_cycle(dependencies, &done));
+            };
+
+            done[next] = true;
+            order.push(next);
+
+            for &dependent in &dependents[next] {
+                remaining[dependent] -= 1;
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Every system left over after sorting waits on another one that is left
+    /// over, so walking backwards through those has to run into a cycle.
+    fn find_cycle(&self, dependencies: &[Vec<usize>], done: &[bool]) -> ScheduleError {
+        let mut path = Vec::new();
+        let mut current = done.iter().position(|&done| !done).unwrap();
+
+        while !path.contains(&current) {
+            path.push(current);
+            current = *dependencies[current]
+                .iter()
+                .find(|&&dependency| !done[dependency])
+                .unwrap();
+        }
+
+        let start = path.iter().position(|&index| index == current).unwrap();
+
+        let mut cycle = path.split_off(start);
+        cycle.reverse();
+
+        // Start from the system that was added first, so the same cycle is
+        // always reported the same way.
+        let first = cycle
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, index)| index)
+            .unwrap()
+            .0;
+        cycle.rotate_left(first);
+
+        let cycle = cycle
+            .iter()
+            .map(|&index| self.systems[index].system.name().to_owned())
+            .collect();
+
+        ScheduleError::Cycle(cycle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::Query;
+
+    #[derive(Debug)]
+    struct Position;
+
+    fn input(_query: Query<&Position>) {}
+
+    fn physics(_query: Query<&mut Position>) {}
+
+    fn render(_query: Query<&Position>) {}
+
+    fn names(schedule: &Schedule) -> Vec<&str> {
+        schedule
+            .systems()
+            .map(|system| system.name().rsplit("::").next().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_before_after() {
+        let mut schedule = Schedule::new();
+
+        schedule
+            .add_system(render.after(physics))
+            .add_system(physics)
+            .add_system(input.before(physics));
+
+        schedule.build().unwrap();
+
+        assert_eq!(names(&schedule), ["input", "physics", "render"]);
+    }
+
+    #[test]
+    fn test_sets() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let logger = |name: &'static str| {
+            let log = Arc::clone(&log);
+            move |_query: Query<&Position>| log.lock().unwrap().push(name)
+        };
+
+        let mut schedule = Schedule::new();
+
+        schedule
+            .add_set(SystemSet::new("late").after("early"))
+            .add_system(logger("c").in_set("late"))
+            .add_system(logger("d").in_set("late"))
+            .add_system(logger("a").in_set("early"))
+            .add_system(logger("b").in_set("early"));
+
+        let mut world = World::new();
+
+        schedule.run(&mut world).unwrap();
+        schedule.run(&mut world).unwrap();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["a", "b", "c", "d", "a", "b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut schedule = Schedule::new();
+
+        schedule
+            .add_system(input.before(physics))
+            .add_system(physics.in_set("simulation"))
+            .add_system(render.before(input).after("simulation"));
+
+        let Err(ScheduleError::Cycle(cycle)) = schedule.build() else {
+            panic!("expected a cycle");
+        };
+
+        let cycle = cycle
+            .iter()
+            .map(|name| name.rsplit("::").next().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(cycle, ["input", "physics", "render"]);
+        assert!(schedule.run(&mut World::new()).is_err());
+    }
+
+    #[test]
+    fn test_unknown_label() {
+        let mut schedule = Schedule::new();
+
+        schedule.add_system(input.before("missing"));
+
+        assert_eq!(
+            schedule.build(),
+            Err(ScheduleError::UnknownLabel("missing".into_label()))
+        );
+    }
+}
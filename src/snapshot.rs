@@ -0,0 +1,611 @@
+use std::{
+    alloc,
+    error::Error,
+    fmt,
+    mem::ManuallyDrop,
+    ptr::{self, NonNull},
+};
+
+use rustc_hash::FxHashSet;
+
+use crate::{
+    Children, Component, ComponentDescriptor, ComponentTicks, Entity, Location, Parent, TypeInfo,
+    Value, World,
+};
+
+/// A component that can be written to snapshots and read back, once it's
+/// registered with [`World::register_snapshot`].
+pub trait SnapshotComponent: Component + Sized {
+    fn to_value(&self) -> Value;
+
+    /// Returns `None` if `value` doesn't describe a valid component.
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl SnapshotComponent for Parent {
+    fn to_value(&self) -> Value {
+        self.0.into()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_entity().map(Parent)
+    }
+}
+
+impl SnapshotComponent for Children {
+    fn to_value(&self) -> Value {
+        self.0.clone().into()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let children = value.as_list()?.iter().map(Value::as_entity);
+
+        children.collect::<Option<_>>().map(Children)
+    }
+}
+
+/// Why a snapshot couldn't be taken or loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    /// The text isn't valid syntax. Lines and columns count from 1.
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The text parsed, but isn't laid out like a snapshot.
+    Malformed(String),
+    /// The snapshot has a component type that the world hasn't registered
+    /// with [`World::register_snapshot`].
+    UnknownComponent(String),
+    /// A component's value couldn't be read back.
+    InvalidValue { component: String, entity: Entity },
+    /// A binary [`WorldSnapshot`](crate::WorldSnapshot) was taken of a world
+    /// with values of a component type that isn't registered with
+    /// [`World::register_pod`].
+    NotPod(String),
+    /// Snapshots can only be loaded into a world that has never had entities.
+    NotEmpty,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "{} at line {}, column {}", message, line, column),
+            SnapshotError::Malformed(message) => write!(f, "malformed snapshot: {}", message),
+            SnapshotError::UnknownComponent(name) => {
+                write!(f, "component `{}` isn't registered for snapshots", name)
+            }
+            SnapshotError::InvalidValue { component, entity } => {
+                write!(f, "invalid `{}` on entity {:?}", component, entity)
+            }
+            SnapshotError::NotPod(name) => {
+                write!(f, "component `{}` isn't registered as plain data", name)
+            }
+            SnapshotError::NotEmpty => {
+                write!(f, "snapshots can only be loaded into an empty world")
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+/// Writes every entity, and each component whose type is registered for
+/// snapshots, as a [`Value`].
+pub(crate) fn save(world: &World) -> Value {
+    let components = world.components();
+
+    let saved = |info: &TypeInfo| {
+        let descriptor = components.get_by_type_id(info.id())?;
+
+        Some((descriptor.type_name(), descriptor.to_value_fn()?))
+    };
+
+    let entities = Value::map([
+        (
+            "generations",
+            Value::List(
+                world
+                    .entities
+                    .entities
+                    .iter()
+                    .map(|meta| meta.generation.into())
+                    .collect(),
+            ),
+        ),
+        (
+            "freed",
+            Value::List(
+                world
+                    .entities
+                    .freed()
+                    .iter()
+                    .map(|&id| Value::Int(id as i64))
+                    .collect(),
+            ),
+        ),
+        ("count", Value::Int(world.entities.count() as i64)),
+    ]);
+
+    let archetypes = world.archetypes.archetypes.iter().map(|archetype| {
+        let columns = archetype.components.iter().filter_map(|column| {
+            let (name, to_value) = saved(column.info())?;
+            let values =
+                (0..column.len()).map(|row| unsafe { to_value(column.get_unchecked(row)) });
+
+            Some((name, Value::List(values.collect())))
+        });
+
+        Value::map([
+            ("entities", Value::from(archetype.entities().to_vec())),
+            ("columns", Value::map(columns)),
+        ])
+    });
+
+    let mut sparse_sets = world
+        .sparse_sets
+        .iter()
+        .filter_map(|set| Some((components.get_id(set.info().id())?, set)))
+        .collect::<Vec<_>>();
+    sparse_sets.sort_by_key(|&(id, _)| id);
+
+    let sparse_sets = sparse_sets.into_iter().filter_map(|(_, set)| {
+        let (name, to_value) = saved(set.info())?;
+        let column = set.column();
+        let values = (0..column.len()).map(|row| unsafe { to_value(column.get_unchecked(row)) });
+
+        Some((
+            name,
+            Value::map([
+                ("entities", Value::from(set.entities().to_vec())),
+                ("values", Value::List(values.collect())),
+            ]),
+        ))
+    });
+
+    Value::map([
+        ("entities", entities),
+        ("archetypes", Value::List(archetypes.collect())),
+        ("sparse_sets", Value::map(sparse_sets)),
+    ])
+}
+
+/// Loads a snapshot written by [`save`] into an empty world. Everything is
+/// read before the world is touched, so on error the world is left as it was.
+pub(crate) fn load(world: &mut World, snapshot: &Value) -> Result<(), SnapshotError> {
+    if !world.entities.entities.is_empty() || world.entities.needs_flush() {
+        return Err(SnapshotError::NotEmpty);
+    }
+
+    let entities = field(snapshot, "entities")?;
+
+    let generations = list(entities, "generations")?
+        .iter()
+        .map(|value| u32::try_from(value.as_i64()?).ok())
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| malformed("`generations` must hold generations"))?;
+
+    let freed = list(entities, "freed")?
+        .iter()
+        .map(|value| usize::try_from(value.as_i64()?).ok())
+        .collect::<Option<Vec<_>>>()
+        .filter(|freed| freed.iter().all(|&id| id < generations.len()))
+        .ok_or_else(|| malformed("`freed` must hold entity indices"))?;
+
+    let count = field(entities, "count")?
+        .as_i64()
+        .and_then(|count| usize::try_from(count).ok())
+        .ok_or_else(|| malformed("`count` must be a number"))?;
+
+    let freed_ids = freed.iter().copied().collect::<FxHashSet<_>>();
+
+    if freed_ids.len() != freed.len() || count < generations.len() {
+        return Err(malformed("`freed` and `count` don't match `generations`"));
+    }
+
+    let mut placed = FxHashSet::default();
+
+    // Every entity must exist with the saved generation, and can only be in
+    // one row.
+    let mut read_entity = |value: &Value, unique: bool| {
+        value
+            .as_entity()
+            .filter(|entity| {
+                generations.get(entity.id()) == Some(&entity.generation())
+                    && !freed_ids.contains(&entity.id())
+                    && placed.insert(entity.id()) == unique
+            })
+            .ok_or_else(|| malformed(&format!("invalid entity {}", value)))
+    };
+
+    let mut live = 0;
+    let mut table_types = FxHashSet::default();
+    let mut tables = Vec::new();
+
+    for archetype in list(snapshot, "archetypes")? {
+        let entities = list(archetype, "entities")?
+            .iter()
+            .map(|value| read_entity(value, true))
+            .collect::<Result<Vec<_>, _>>()?;
+        live += entities.len();
+
+        let mut columns = Vec::new();
+
+        for (name, values) in map(archetype, "columns")? {
+            let descriptor = descriptor(world, name)?;
+            let values = values.as_list().unwrap_or_default();
+
+            if world.sparse_sets.contains(descriptor.type_id()) {
+                return Err(malformed(&format!(
+                    "component `{}` is stored in a sparse set",
+                    name
+                )));
+            }
+
+            if values.len() != entities.len()
+                || columns
+                    .iter()
+                    .any(|(info, _): &(TypeInfo, _)| info.id() == descriptor.type_id())
+            {
+                return Err(malformed(&format!("invalid column `{}`", name)));
+            }
+
+            let values = values
+                .iter()
+                .zip(&entities)
+                .map(|(value, &entity)| OwnedComponent::read(descriptor, value, entity))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            table_types.insert(descriptor.type_id());
+            columns.push((*descriptor.info(), values));
+        }
+
+        tables.push((entities, columns));
+    }
+
+    if live + freed.len() != generations.len() {
+        return Err(malformed("every live entity must be in an archetype"));
+    }
+
+    let mut sparse_sets = Vec::new();
+
+    for (name, set) in snapshot
+        .get("sparse_sets")
+        .and_then(Value::as_map)
+        .unwrap_or_default()
+    {
+        let descriptor = descriptor(world, name)?;
+
+        if !world.sparse_sets.contains(descriptor.type_id()) {
+            return Err(malformed(&format!(
+                "component `{}` is stored in a table",
+                name
+            )));
+        }
+
+        let entities = list(set, "entities")?
+            .iter()
+            .map(|value| read_entity(value, false))
+            .collect::<Result<Vec<_>, _>>()?;
+        let values = list(set, "values")?;
+
+        if values.len() != entities.len() || table_types.contains(&descriptor.type_id()) {
+            return Err(malformed(&format!("invalid sparse set `{}`", name)));
+        }
+
+        let values = values
+            .iter()
+            .zip(&entities)
+            .map(|(value, &entity)| OwnedComponent::read(descriptor, value, entity))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        sparse_sets.push((*descriptor.info(), entities.into_iter().zip(values)));
+    }
+
+    // Nothing can fail from here on.
+    let ticks = ComponentTicks::new(world.change_tick());
+
+    world.entities.restore(generations, freed, count);
+
+    for (entities, columns) in tables {
+        let infos = columns.iter().map(|(info, _)| *info).collect::<Vec<_>>();
+        let id = world.archetypes.get_or_create(infos);
+        let archetype = world.archetypes.get_by_id_mut(id);
+
+        let mut columns = columns
+            .into_iter()
+            .map(|(info, values)| (info, values.into_iter()))
+            .collect::<Vec<_>>();
+
+        for entity in entities {
+            for (info, values) in &mut columns {
+                let value = values.next().unwrap();
+
+                unsafe { value.take(|ptr| archetype.put(info.id(), ptr, ticks)) };
+            }
+
+            archetype.entities.push(entity);
+
+            let row = archetype.row();
+            world.entities.set_location(entity, Location { row, id });
+        }
+    }
+
+    for (info, values) in sparse_sets {
+        let set = world.sparse_sets.get_mut(info.id()).unwrap();
+
+        for (entity, value) in values {
+            unsafe { value.take(|ptr| set.insert(entity, ptr, ticks)) };
+        }
+    }
+
+    Ok(())
+}
+
+fn malformed(message: &str) -> SnapshotError {
+    SnapshotError::Malformed(message.to_owned())
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Result<&'a Value, SnapshotError> {
+    value
+        .get(key)
+        .ok_or_else(|| malformed(&format!("missing `{}`", key)))
+}
+
+fn list<'a>(value: &'a Value, key: &str) -> Result<&'a [Value], SnapshotError> {
+    field(value, key)?
+        .as_list()
+        .ok_or_else(|| malformed(&format!("`{}` must be a list", key)))
+}
+
+fn map<'a>(value: &'a Value, key: &str) -> Result<&'a [(String, Value)], SnapshotError> {
+    field(value, key)?
+        .as_map()
+        .ok_or_else(|| malformed(&format!("`{}` must be a map", key)))
+}
+
+fn descriptor<'w>(world: &'w World, name: &str) -> Result<&'w ComponentDescriptor, SnapshotError> {
+    world
+        .components()
+        .get_by_name(name)
+        .filter(|descriptor| descriptor.from_value_fn().is_some())
+        .ok_or_else(|| SnapshotError::UnknownComponent(name.to_owned()))
+}
+
+/// A component read from a snapshot, in its own allocation until it's moved
+/// into the world.
+struct OwnedComponent {
+    info: TypeInfo,
+    ptr: NonNull<u8>,
+}
+
+impl OwnedComponent {
+    fn read(
+        descriptor: &ComponentDescriptor,
+        value: &Value,
+        entity: Entity,
+    ) -> Result<Self, SnapshotError> {
+        let info = *descriptor.info();
+        let layout = info.layout();
+
+        let ptr = if layout.size() == 0 {
+            NonNull::new(ptr::without_provenance_mut(layout.align())).unwrap()
+        } else {
+            let ptr = unsafe { alloc::alloc(layout) };
+            NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+        };
+
+        let from_value = descriptor.from_value_fn().unwrap();
+
+        if unsafe { from_value(value, ptr.as_ptr()) } {
+            return Ok(Self { info, ptr });
+        }
+
+        if layout.size() != 0 {
+            unsafe { alloc::dealloc(ptr.as_ptr(), layout) };
+        }
+
+        Err(SnapshotError::InvalidValue {
+            component: info.type_name().to_owned(),
+            entity,
+        })
+    }
+
+    /// Passes the value to `f`, then frees the allocation.
+    ///
+    /// # Safety
+    /// `f` must take ownership of the value.
+    unsafe fn take(self, f: impl FnOnce(*mut u8)) {
+        let this = ManuallyDrop::new(self);
+
+        f(this.ptr.as_ptr());
+        this.dealloc();
+    }
+
+    fn dealloc(&self) {
+        if self.info.layout().size() != 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), self.info.layout()) };
+        }
+    }
+}
+
+impl Drop for OwnedComponent {
+    fn drop(&mut self) {
+        unsafe { self.info.drop(self.ptr.as_ptr()) };
+        self.dealloc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StorageType;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Position(f32, f32);
+
+    impl SnapshotComponent for Position {
+        fn to_value(&self) -> Value {
+            Value::map([("x", self.0.into()), ("y", self.1.into())])
+        }
+
+        fn from_value(value: &Value) -> Option<Self> {
+            Some(Position(
+                value.get("x")?.as_f64()? as f32,
+                value.get("y")?.as_f64()? as f32,
+            ))
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Name(String);
+
+    impl SnapshotComponent for Name {
+        fn to_value(&self) -> Value {
+            self.0.as_str().into()
+        }
+
+        fn from_value(value: &Value) -> Option<Self> {
+            value.as_str().map(|name| Name(name.to_owned()))
+        }
+    }
+
+    #[derive(Debug)]
+    struct Selected;
+
+    impl SnapshotComponent for Selected {
+        fn to_value(&self) -> Value {
+            Value::Null
+        }
+
+        fn from_value(_value: &Value) -> Option<Self> {
+            Some(Selected)
+        }
+    }
+
+    fn new_world() -> World {
+        let mut world = World::new();
+
+        world.register_component::<Selected>(StorageType::SparseSet);
+        world.register_snapshot::<Position>();
+        world.register_snapshot::<Name>();
+        world.register_snapshot::<Selected>();
+        world.register_snapshot::<Parent>();
+        world.register_snapshot::<Children>();
+
+        world
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut world = new_world();
+
+        let a = world.spawn((Position(1.0, 2.0), Name("tank".into())));
+        let b = world.spawn((Position(3.0, 4.0),));
+        let c = world.spawn((Name("gone".into()),));
+        let d = world.spawn((Name("turret \"1\"".into()), Selected));
+
+        world.set_parent(d, a);
+        world.remove_entity(c);
+        world.remove_entity(b);
+
+        let text = world.save_snapshot();
+
+        let mut loaded = new_world();
+        loaded.load_snapshot(&text).unwrap();
+
+        for entity in [a, d] {
+            let (before, after) = (world.entity(entity), loaded.entity(entity));
+
+            assert_eq!(after.row(), before.row());
+            assert_eq!(
+                world.archetypes.get_by_id(before.archetype()).types,
+                loaded.archetypes.get_by_id(after.archetype()).types
+            );
+        }
+
+        assert!(loaded.get_entity(b).is_none());
+        assert!(loaded.get_entity(c).is_none());
+
+        assert_eq!(loaded.get::<Position>(a), Some(&Position(1.0, 2.0)));
+        assert_eq!(loaded.get::<Name>(d), Some(&Name("turret \"1\"".into())));
+        assert!(loaded.entity(d).has_component::<Selected>());
+        assert_eq!(loaded.ancestors(d).collect::<Vec<_>>(), [a]);
+
+        // Freed indices are reused in the same order.
+        assert_eq!(loaded.spawn((Selected,)), world.spawn((Selected,)));
+        assert_eq!(loaded.save_snapshot(), world.save_snapshot());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut world = new_world();
+        let entity = world.spawn((Position(1.0, 2.0),));
+
+        let text = world.save_snapshot();
+
+        let mut unregistered = World::new();
+
+        assert_eq!(
+            unregistered.load_snapshot(&text),
+            Err(SnapshotError::UnknownComponent(
+                std::any::type_name::<Position>().into()
+            ))
+        );
+        assert!(unregistered.archetypes.archetypes.is_empty());
+
+        assert_eq!(
+            new_world().load_snapshot(&text.replace("\"x\": 1.0", "\"x\": true")),
+            Err(SnapshotError::InvalidValue {
+                component: std::any::type_name::<Position>().into(),
+                entity
+            })
+        );
+
+        assert!(matches!(
+            new_world().load_snapshot("{\"entities\": 1}"),
+            Err(SnapshotError::Malformed(_))
+        ));
+        assert!(matches!(
+            new_world().load_snapshot("{"),
+            Err(SnapshotError::Syntax { .. })
+        ));
+        assert_eq!(world.load_snapshot(&text), Err(SnapshotError::NotEmpty));
+    }
+
+    #[test]
+    fn test_storage_mismatch() {
+        let mut world = new_world();
+        let entity = world.spawn((Position(1.0, 2.0), Selected));
+
+        let text = world.save_snapshot();
+
+        // Each world keeps the storage it registered for the type.
+        let mut tables = World::new();
+        tables.register_snapshot::<Position>();
+        tables.register_snapshot::<Selected>();
+
+        assert!(matches!(
+            tables.load_snapshot(&text),
+            Err(SnapshotError::Malformed(_))
+        ));
+        assert_eq!(tables.storage_type::<Selected>(), StorageType::Table);
+
+        world.remove_entity(entity);
+        world.spawn((Position(1.0, 2.0),));
+
+        let mut sparse = new_world();
+        sparse.register_component::<Position>(StorageType::SparseSet);
+
+        assert!(matches!(
+            sparse.load_snapshot(&world.save_snapshot()),
+            Err(SnapshotError::Malformed(_))
+        ));
+        assert_eq!(sparse.storage_type::<Position>(), StorageType::SparseSet);
+    }
+}
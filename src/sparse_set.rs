@@ -1,7 +1,12 @@
 use core::slice;
-use std::fmt::{self, Debug};
+use std::{
+    any::TypeId,
+    fmt::{self, Debug},
+};
 
-use crate::{Component, Entity};
+use rustc_hash::FxHashMap;
+
+use crate::{Column, Component, ComponentTicks, Entity, TypeInfo};
 
 pub struct SparseSet<T> {
     sparse: SparseArray<usize>,
@@ -46,9 +51,7 @@ impl<T: Component> SparseSet<T> {
     }
 
     pub fn remove(&mut self, entity: Entity) -> Option<T> {
-        let index = self.sparse.get(entity.id());
-
-        if let Some(index) = index.copied() {
+        if let Some(index) = self.index_of(entity) {
             let component = self.data.swap_remove(index);
             let _entity = self.dense.swap_remove(index);
 
@@ -67,19 +70,27 @@ impl<T: Component> SparseSet<T> {
     }
 
     pub fn get(&self, entity: Entity) -> Option<&T> {
-        let index = self.sparse.get(entity.id())?;
+        let index = self.index_of(entity)?;
 
-        self.data.get(*index)
+        self.data.get(index)
     }
 
     pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
-        let index = self.sparse.get(entity.id())?;
+        let index = self.index_of(entity)?;
 
-        self.data.get_mut(*index)
+        self.data.get_mut(index)
     }
 
     pub fn contains(&self, entity: Entity) -> bool {
-        self.sparse.contains(entity.id())
+        self.index_of(entity).is_some()
+    }
+
+    /// Looks up the dense index of the entity, ignoring stale handles whose
+    /// index has since been reused by a newer generation.
+    fn index_of(&self, entity: Entity) -> Option<usize> {
+        let index = *self.sparse.get(entity.id())?;
+
+        (self.dense[index] == entity).then_some(index)
     }
 
     pub fn components(&self) -> &[T] {
@@ -99,6 +110,12 @@ impl<T: Component> SparseSet<T> {
     }
 }
 
+impl<T: Component> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Debug> fmt::Debug for SparseSet<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("SparseArray")
@@ -107,7 +124,7 @@ impl<T: Debug> fmt::Debug for SparseSet<T> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SparseArray<T> {
     values: Vec<Option<T>>,
 }
@@ -153,6 +170,237 @@ impl<T> SparseArray<T> {
     pub fn len(&self) -> usize {
         self.values.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl<T> Default for SparseArray<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A sparse set of one component type, stored type-erased in a [`Column`].
+///
+/// Backs the components registered with
+/// [`StorageType::SparseSet`](crate::StorageType::SparseSet), which are kept
+/// out of archetype tables, so adding or removing them never moves an entity
+/// between archetypes.
+pub struct ComponentSparseSet {
+    sparse: SparseArray<usize>,
+    dense: Vec<Entity>,
+    column: Column,
+}
+
+impl ComponentSparseSet {
+    pub fn new(info: TypeInfo) -> Self {
+        Self {
+            sparse: SparseArray::new(),
+            dense: Vec::new(),
+            column: Column::new(info),
+        }
+    }
+
+    pub fn info(&self) -> &TypeInfo {
+        self.column.info()
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.index_of(entity).is_some()
+    }
+
+    /// The entities in the set, in the same order as the rows of
+    /// [`ComponentSparseSet::column`].
+    pub fn entities(&self) -> &[Entity] {
+        &self.dense
+    }
+
+    pub fn column(&self) -> &Column {
+        &self.column
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        let index = self.index_of(entity)?;
+
+        self.column.as_slice::<T>().get(index)
+    }
+
+    pub fn get_with_ticks_mut<T: Component>(
+        &mut self,
+        entity: Entity,
+    ) -> Option<(&mut T, &mut ComponentTicks)> {
+        let index = self.index_of(entity)?;
+
+        self.column.get_with_ticks_mut(index)
+    }
+
+    pub fn get_ticks(&self, entity: Entity) -> Option<ComponentTicks> {
+        self.column.get_ticks(self.index_of(entity)?)
+    }
+
+    /// Looks up the row of the entity in the column, ignoring stale handles.
+    pub(crate) fn index_of(&self, entity: Entity) -> Option<usize> {
+        let index = *self.sparse.get(entity.id())?;
+
+        (self.dense[index] == entity).then_some(index)
+    }
+
+    /// Moves the value behind `ptr` into the set. If the entity already has a
+    /// value, it's dropped and replaced, keeping the tick it was added at.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid value of the set's type, which the caller
+    /// must not use or drop afterwards.
+    pub(crate) unsafe fn insert(&mut self, entity: Entity, ptr: *const u8, ticks: ComponentTicks) {
+        match self.index_of(entity) {
+            Some(index) => self.column.replace(index, ptr, ticks.changed),
+            None => {
+                self.sparse.insert(entity.id(), self.dense.len());
+                self.dense.push(entity);
+                self.column.push(ptr, ticks);
+            }
+        }
+    }
+
+    /// Removes the entity's value without dropping it, passing it to `f`
+    /// instead. Returns `false` if the entity isn't in the set.
+    ///
+    /// # Safety
+    /// `f` must move or drop the value it is given.
+    pub(crate) unsafe fn remove_with(&mut self, entity: Entity, f: impl FnOnce(*mut u8)) -> bool {
+        let Some(index) = self.index_of(entity) else {
+            return false;
+        };
+
+        f(self.column.get_unchecked(index));
+        self.column.swap_remove_forget(index);
+
+        self.dense.swap_remove(index);
+        self.sparse.remove(entity.id());
+
+        if let Some(&swapped) = self.dense.get(index) {
+            self.sparse.insert(swapped.id(), index);
+        }
+
+        true
+    }
+
+    /// Replaces the contents of the set with the given entities and copies of
+    /// their values.
+    ///
+    /// # Safety
+    /// As for [`Column::copy_from`], with one entity per entry in `ticks`.
+    pub(crate) unsafe fn copy_from(
+        &mut self,
+        entities: &[Entity],
+        bytes: &[u8],
+        ticks: &[ComponentTicks],
+    ) {
+        for entity in self.dense.drain(..) {
+            self.sparse.remove(entity.id());
+        }
+
+        for (index, &entity) in entities.iter().enumerate() {
+            self.sparse.insert(entity.id(), index);
+        }
+
+        self.dense.extend_from_slice(entities);
+        self.column.copy_from(bytes, ticks);
+    }
+
+    /// Creates a copy of the set, making its column with `f`.
+    pub(crate) fn clone_with(&self, f: impl FnOnce(&Column) -> Column) -> Self {
+        Self {
+            sparse: self.sparse.clone(),
+            dense: self.dense.clone(),
+            column: f(&self.column),
+        }
+    }
+
+    /// Drops the entity's value. Returns `false` if the entity isn't in the
+    /// set.
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let info = *self.info();
+
+        unsafe { self.remove_with(entity, |ptr| info.drop(ptr)) }
+    }
+}
+
+impl fmt::Debug for ComponentSparseSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentSparseSet")
+            .field("type", self.info())
+            .field("entities", &self.dense)
+            .field("values", &self.column)
+            .finish()
+    }
+}
+
+/// The sparse sets of every component type registered with
+/// [`StorageType::SparseSet`](crate::StorageType::SparseSet).
+#[derive(Default)]
+pub struct SparseSets {
+    sets: FxHashMap<TypeId, ComponentSparseSet>,
+}
+
+impl SparseSets {
+    pub fn contains(&self, type_id: TypeId) -> bool {
+        self.sets.contains_key(&type_id)
+    }
+
+    pub fn get(&self, type_id: TypeId) -> Option<&ComponentSparseSet> {
+        self.sets.get(&type_id)
+    }
+
+    pub fn get_mut(&mut self, type_id: TypeId) -> Option<&mut ComponentSparseSet> {
+        self.sets.get_mut(&type_id)
+    }
+
+    pub(crate) fn get_or_insert(&mut self, info: TypeInfo) -> &mut ComponentSparseSet {
+        self.sets
+            .entry(info.id())
+            .or_insert_with(|| ComponentSparseSet::new(info))
+    }
+
+    pub(crate) fn remove(&mut self, type_id: TypeId) -> Option<ComponentSparseSet> {
+        self.sets.remove(&type_id)
+    }
+
+    /// Creates a copy of every set, making each column with `f`.
+    pub(crate) fn clone_with(&self, mut f: impl FnMut(&Column) -> Column) -> Self {
+        let sets = self
+            .sets
+            .iter()
+            .map(|(&type_id, set)| (type_id, set.clone_with(&mut f)));
+
+        Self {
+            sets: sets.collect(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentSparseSet> {
+        self.sets.values()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut ComponentSparseSet> {
+        self.sets.values_mut()
+    }
+}
+
+impl fmt::Debug for SparseSets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.sets.values()).finish()
+    }
 }
 
 #[cfg(test)]
@@ -247,7 +495,7 @@ mod tests {
         assert!(sparse_set.contains(Entity::from_raw(1)));
         assert!(sparse_set.contains(Entity::from_raw(2)));
 
-        assert!(sparse_set.contains(Entity::from_raw(3)) == false);
+        assert!(!sparse_set.contains(Entity::from_raw(3)));
     }
 
     #[test]
@@ -256,4 +504,42 @@ mod tests {
 
         assert_eq!(sparse_set.capacity(), 100);
     }
+
+    #[test]
+    fn test_component_sparse_set() {
+        let mut set = ComponentSparseSet::new(TypeInfo::of::<u32>());
+        let ticks = ComponentTicks::new(crate::Tick::new(1));
+
+        for (id, value) in [(3, 30u32), (7, 70), (5, 50)] {
+            unsafe {
+                set.insert(
+                    Entity::from_raw(id),
+                    &value as *const u32 as *const u8,
+                    ticks,
+                )
+            };
+        }
+
+        let replaced = ComponentTicks::new(crate::Tick::new(2));
+        unsafe {
+            set.insert(
+                Entity::from_raw(7),
+                &71u32 as *const u32 as *const u8,
+                replaced,
+            )
+        };
+
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.get::<u32>(Entity::from_raw(7)), Some(&71));
+        assert_eq!(
+            set.get_ticks(Entity::from_raw(7)).unwrap().added,
+            crate::Tick::new(1)
+        );
+
+        assert!(set.remove(Entity::from_raw(3)));
+        assert!(!set.remove(Entity::from_raw(3)));
+
+        assert_eq!(set.entities(), [Entity::from_raw(5), Entity::from_raw(7)]);
+        assert_eq!(set.get::<u32>(Entity::from_raw(5)), Some(&50));
+    }
 }
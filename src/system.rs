@@ -0,0 +1,694 @@
+use std::{
+    any::{type_name, TypeId},
+    borrow::Cow,
+    marker::PhantomData,
+};
+
+use crate::{
+    Access, CommandQueue, Commands, ComponentsQuery, Event, EventCursor, EventReader, EventWriter,
+    Events, Query, QueryFilter, QueryState, Res, ResMut, Resource, Tick, World,
+};
+
+/// A unit of game logic that runs against a [`World`].
+///
+/// Systems declare up front which components they read and write, so that
+/// whoever runs them can tell which systems may run at the same time.
+pub trait System: Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    /// Every component type the system reads or writes.
+    fn access(&self) -> &Access;
+
+    /// Runs the system through a shared reference to the world.
+    ///
+    /// # Safety
+    /// The caller must ensure nothing else accesses the components the system
+    /// writes, or writes the components it reads, while it runs.
+    unsafe fn run_unsafe(&mut self, world: &World);
+
+    /// Applies the changes the system deferred while it ran, such as its
+    /// [`Commands`].
+    fn apply_deferred(&mut self, world: &mut World);
+
+    /// Runs the system and applies its deferred changes right away.
+    fn run(&mut self, world: &mut World) {
+        unsafe { self.run_unsafe(world) };
+        self.apply_deferred(world);
+    }
+}
+
+/// Converts something into a [`System`]. `Marker` only exists to keep the
+/// implementations for functions with different parameters apart.
+pub trait IntoSystem<Marker> {
+    type System: System;
+
+    fn into_system(self) -> Self::System;
+}
+
+impl<S: System> IntoSystem<()> for S {
+    type System = S;
+
+    fn into_system(self) -> S {
+        self
+    }
+}
+
+/// What a system knows about itself while fetching its parameters.
+#[derive(Debug)]
+pub struct SystemMeta {
+    name: Cow<'static, str>,
+    access: Access,
+    last_run: Tick,
+}
+
+impl SystemMeta {
+    fn new<T>() -> Self {
+        Self {
+            name: type_name::<T>().into(),
+            access: Access::new(),
+            last_run: Tick::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// The tick the system last ran at. Change filters in its queries report
+    /// changes made after it.
+    pub fn last_run(&self) -> Tick {
+        self.last_run
+    }
+
+    /// Adds `access` to the system's access, panicking if it conflicts with
+    /// what an earlier parameter already accesses.
+    pub fn add_access(&mut self, access: &Access, param: &str) {
+        assert!(
+            self.access.is_compatible(access),
+            "{} in system `{}` conflicts with a previous parameter",
+            param,
+            self.name
+        );
+
+        self.access.extend(access);
+    }
+}
+
+/// Something a function system can take as a parameter.
+///
+/// # Safety
+/// [`SystemParam::update_access`] must report every component
+/// [`SystemParam::get_param`] reads or writes.
+pub unsafe trait SystemParam: Sized {
+    /// Kept by the system between runs.
+    type State: Send + Sync + 'static;
+
+    /// The parameter as the function receives it.
+    type Item<'w, 's>: SystemParam<State = Self::State>;
+
+    fn update_access(system: &mut SystemMeta);
+
+    fn init_state(world: &World) -> Self::State;
+
+    /// # Safety
+    /// Nothing else may access the components the parameter writes, or write
+    /// the components it reads, for as long as the item lives.
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        system: &SystemMeta,
+        world: &'w World,
+        this_run: Tick,
+    ) -> Self::Item<'w, 's>;
+
+    /// Applies what the parameter deferred while the system ran.
+    fn apply(_state: &mut Self::State, _world: &mut World) {}
+}
+
+pub type SystemParamItem<'w, 's, P> = <P as SystemParam>::Item<'w, 's>;
+
+unsafe impl<T, F> SystemParam for Query<'_, '_, T, F>
+where
+    T: ComponentsQuery + 'static,
+    F: QueryFilter + 'static,
+{
+    type State = QueryState<T, F>;
+    type Item<'w, 's> = Query<'w, 's, T, F>;
+
+    fn update_access(system: &mut SystemMeta) {
+        let access = QueryState::<T, F>::access_of();
+
+        system.add_access(&access, type_name::<Self>());
+    }
+
+    fn init_state(world: &World) -> Self::State {
+        QueryState::new(world)
+    }
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        system: &SystemMeta,
+        world: &'w World,
+        this_run: Tick,
+    ) -> Self::Item<'w, 's> {
+        state.update_archetypes(world);
+        state.query_unchecked(world, system.last_run, this_run)
+    }
+}
+
+unsafe impl<R: Resource> SystemParam for Res<'_, R> {
+    type State = ();
+    type Item<'w, 's> = Res<'w, R>;
+
+    fn update_access(system: &mut SystemMeta) {
+        let mut access = Access::new();
+        access.add_resource_read(TypeId::of::<R>());
+
+        system.add_access(&access, type_name::<Self>());
+    }
+
+    fn init_state(_world: &World) {}
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut (),
+        system: &SystemMeta,
+        world: &'w World,
+        this_run: Tick,
+    ) -> Self::Item<'w, 's> {
+        let (value, ticks) = world
+            .resources
+            .get_unchecked::<R>()
+            .unwrap_or_else(|| missing_resource::<R>(system));
+
+        Res::new(&*value, *ticks, system.last_run, this_run)
+    }
+}
+
+unsafe impl<R: Resource> SystemParam for ResMut<'_, R> {
+    type State = ();
+    type Item<'w, 's> = ResMut<'w, R>;
+
+    fn update_access(system: &mut SystemMeta) {
+        let mut access = Access::new();
+        access.add_resource_write(TypeId::of::<R>());
+
+        system.add_access(&access, type_name::<Self>());
+    }
+
+    fn init_state(_world: &World) {}
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut (),
+        system: &SystemMeta,
+        world: &'w World,
+        this_run: Tick,
+    ) -> Self::Item<'w, 's> {
+        let (value, ticks) = world
+            .resources
+            .get_unchecked::<R>()
+            .unwrap_or_else(|| missing_resource::<R>(system));
+
+        ResMut::new(&mut *value, &mut *ticks, system.last_run, this_run)
+    }
+}
+
+unsafe impl<T: Event> SystemParam for EventReader<'_, '_, T> {
+    type State = EventCursor<T>;
+    type Item<'w, 's> = EventReader<'w, 's, T>;
+
+    fn update_access(system: &mut SystemMeta) {
+        Res::<Events<T>>::update_access(system);
+    }
+
+    fn init_state(_world: &World) -> Self::State {
+        EventCursor::default()
+    }
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        system: &SystemMeta,
+        world: &'w World,
+        this_run: Tick,
+    ) -> Self::Item<'w, 's> {
+        let events = Res::<Events<T>>::get_param(&mut (), system, world, this_run);
+
+        EventReader::new(events, state)
+    }
+}
+
+unsafe impl<T: Event> SystemParam for EventWriter<'_, T> {
+    type State = ();
+    type Item<'w, 's> = EventWriter<'w, T>;
+
+    fn update_access(system: &mut SystemMeta) {
+        ResMut::<Events<T>>::update_access(system);
+    }
+
+    fn init_state(_world: &World) {}
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut (),
+        system: &SystemMeta,
+        world: &'w World,
+        this_run: Tick,
+    ) -> Self::Item<'w, 's> {
+        EventWriter::new(ResMut::get_param(state, system, world, this_run))
+    }
+}
+
+// Commands only reserve entities, which is safe to do from several systems at
+// once, so they don't add to the system's access.
+unsafe impl SystemParam for Commands<'_, '_> {
+    type State = CommandQueue;
+    type Item<'w, 's> = Commands<'w, 's>;
+
+    fn update_access(_system: &mut SystemMeta) {}
+
+    fn init_state(_world: &World) -> Self::State {
+        CommandQueue::new()
+    }
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        _system: &SystemMeta,
+        world: &'w World,
+        _this_run: Tick,
+    ) -> Self::Item<'w, 's> {
+        Commands::new(state, world)
+    }
+
+    fn apply(state: &mut Self::State, world: &mut World) {
+        world.apply_commands(state);
+    }
+}
+
+fn missing_resource<R: Resource>(system: &SystemMeta) -> ! {
+    panic!(
+        "resource `{}` requested by system `{}` does not exist",
+        type_name::<R>(),
+        system.name
+    )
+}
+
+macro_rules! impl_system_param {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: SystemParam),*> SystemParam for ($($name,)*) {
+            type State = ($($name::State,)*);
+            type Item<'w, 's> = ($($name::Item<'w, 's>,)*);
+
+            fn update_access(system: &mut SystemMeta) {
+                $($name::update_access(system);)*
+            }
+
+            fn init_state(world: &World) -> Self::State {
+                ($($name::init_state(world),)*)
+            }
+
+            unsafe fn get_param<'w, 's>(
+                state: &'s mut Self::State,
+                system: &SystemMeta,
+                world: &'w World,
+                this_run: Tick,
+            ) -> Self::Item<'w, 's> {
+                let ($($name,)*) = state;
+                ($($name::get_param($name, system, world, this_run),)*)
+            }
+
+            fn apply(state: &mut Self::State, world: &mut World) {
+                let ($($name,)*) = state;
+                $($name::apply($name, world);)*
+            }
+        }
+    };
+}
+
+impl_system_param!();
+impl_system_param!(A);
+impl_system_param!(A, B);
+impl_system_param!(A, B, C);
+impl_system_param!(A, B, C, D);
+impl_system_param!(A, B, C, D, E);
+impl_system_param!(A, B, C, D, E, F);
+impl_system_param!(A, B, C, D, E, F, G);
+impl_system_param!(A, B, C, D, E, F, G, H);
+
+/// A function whose parameters can all be fetched from a [`World`].
+pub trait SystemParamFunction<Marker>: Send + Sync + 'static {
+    type Param: SystemParam;
+
+    fn run(&mut self, param: SystemParamItem<Self::Param>);
+}
+
+macro_rules! impl_system_function {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case, clippy::too_many_arguments)]
+        impl<Func, $($name: SystemParam),*> SystemParamFunction<fn($($name,)*)> for Func
+        where
+            Func: Send + Sync + 'static,
+            for<'a> &'a mut Func: FnMut($($name),*) + FnMut($(SystemParamItem<$name>),*),
+        {
+            type Param = ($($name,)*);
+
+            fn run(&mut self, param: SystemParamItem<($($name,)*)>) {
+                // Calling through a helper pins down which of the two `FnMut`
+                // impls is meant.
+                fn call<$($name),*>(mut f: impl FnMut($($name),*), $($name: $name),*) {
+                    f($($name),*)
+                }
+
+                let ($($name,)*) = param;
+                call(self, $($name),*)
+            }
+        }
+    };
+}
+
+impl_system_function!();
+impl_system_function!(A);
+impl_system_function!(A, B);
+impl_system_function!(A, B, C);
+impl_system_function!(A, B, C, D);
+impl_system_function!(A, B, C, D, E);
+impl_system_function!(A, B, C, D, E, F);
+impl_system_function!(A, B, C, D, E, F, G);
+impl_system_function!(A, B, C, D, E, F, G, H);
+
+/// A [`System`] made from a function, see [`IntoSystem`].
+pub struct FunctionSystem<Marker, Func: SystemParamFunction<Marker>> {
+    func: Func,
+    state: Option<<Func::Param as SystemParam>::State>,
+    meta: SystemMeta,
+    _marker: PhantomData<fn() -> Marker>,
+}
+
+/// Marks the [`IntoSystem`] implementation for functions.
+pub struct IsFunctionSystem;
+
+impl<Marker, Func> IntoSystem<(IsFunctionSystem, Marker)> for Func
+where
+    Marker: 'static,
+    Func: SystemParamFunction<Marker>,
+{
+    type System = FunctionSystem<Marker, Func>;
+
+    fn into_system(self) -> Self::System {
+        let mut meta = SystemMeta::new::<Func>();
+        Func::Param::update_access(&mut meta);
+
+        FunctionSystem {
+            func: self,
+            state: None,
+            meta,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Marker, Func> System for FunctionSystem<Marker, Func>
+where
+    Marker: 'static,
+    Func: SystemParamFunction<Marker>,
+{
+    fn name(&self) -> &str {
+        self.meta.name()
+    }
+
+    fn access(&self) -> &Access {
+        self.meta.access()
+    }
+
+    unsafe fn run_unsafe(&mut self, world: &World) {
+        let this_run = world.increment_change_tick();
+
+        let state = self
+            .state
+            .get_or_insert_with(|| Func::Param::init_state(world));
+
+        let param = Func::Param::get_param(state, &self.meta, world, this_run);
+        self.func.run(param);
+
+        self.meta.last_run = this_run;
+    }
+
+    fn apply_deferred(&mut self, world: &mut World) {
+        if let Some(state) = &mut self.state {
+            Func::Param::apply(state, world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        any::TypeId,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+    use crate::{Changed, Entity, With};
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f32);
+
+    #[derive(Debug)]
+    struct Velocity(f32);
+
+    #[derive(Debug)]
+    struct Frozen;
+
+    fn movement(query: Query<(&mut Position, &Velocity)>) {
+        for (mut position, velocity) in query {
+            position.0 += velocity.0;
+        }
+    }
+
+    #[test]
+    fn test_function_system() {
+        let mut world = World::new();
+
+        world.spawn((Position(0.0), Velocity(1.0)));
+        world.spawn((Position(1.0), Velocity(2.0), Frozen));
+
+        let mut system = movement.into_system();
+
+        system.run(&mut world);
+        system.run(&mut world);
+
+        let positions = world.query::<&Position>().into_iter().collect::<Vec<_>>();
+        assert_eq!(positions, [&Position(2.0), &Position(5.0)]);
+
+        let access = system.access();
+        assert!(access.has_write(TypeId::of::<Position>()));
+        assert!(access.has_read(TypeId::of::<Velocity>()));
+        assert!(!access.has_read(TypeId::of::<Frozen>()));
+    }
+
+    #[test]
+    fn test_multiple_params() {
+        fn thaw(mut frozen: Query<&mut Velocity, With<Frozen>>, positions: Query<&Position>) {
+            let total = positions.iter().map(|position| position.0).sum::<f32>();
+
+            for mut velocity in frozen.iter_mut() {
+                velocity.0 = total;
+            }
+        }
+
+        let mut world = World::new();
+
+        world.spawn((Position(1.0), Velocity(1.0)));
+        let b = world.spawn((Position(2.0), Velocity(0.0), Frozen));
+
+        let mut system = thaw.into_system();
+        system.run(&mut world);
+
+        assert_eq!(world.get::<Velocity>(b).unwrap().0, 3.0);
+    }
+
+    #[test]
+    fn test_last_run() {
+        let mut world = World::new();
+
+        let a = world.spawn((Position(0.0),));
+        world.spawn((Position(1.0),));
+
+        let changed = Arc::new(AtomicUsize::new(0));
+
+        let mut system = {
+            let changed = Arc::clone(&changed);
+
+            (move |query: Query<&Position, Changed<Position>>| {
+                changed.store(query.iter().count(), Ordering::Relaxed);
+            })
+            .into_system()
+        };
+
+        system.run(&mut world);
+        assert_eq!(changed.load(Ordering::Relaxed), 2);
+
+        system.run(&mut world);
+        assert_eq!(changed.load(Ordering::Relaxed), 0);
+
+        world.get_mut::<Position>(a).unwrap().0 += 1.0;
+
+        system.run(&mut world);
+        assert_eq!(changed.load(Ordering::Relaxed), 1);
+    }
+
+    #[derive(Debug)]
+    struct Time(f32);
+
+    #[test]
+    fn test_resources() {
+        fn integrate(time: Res<Time>, mut total: ResMut<f32>, query: Query<&Velocity>) {
+            for velocity in query.iter() {
+                *total += velocity.0 * time.0;
+            }
+        }
+
+        let mut world = World::new();
+
+        world.spawn((Velocity(1.0),));
+        world.spawn((Velocity(2.0),));
+        world.insert_resource(Time(0.5));
+        world.insert_resource(0.0f32);
+
+        let mut system = integrate.into_system();
+
+        system.run(&mut world);
+        system.run(&mut world);
+
+        assert_eq!(*world.resource::<f32>(), 3.0);
+        assert!(system.access().has_resource_read(TypeId::of::<Time>()));
+        assert!(system.access().has_resource_write(TypeId::of::<f32>()));
+    }
+
+    #[test]
+    fn test_resource_changed() {
+        let changed = Arc::new(AtomicUsize::new(0));
+
+        let mut system = {
+            let changed = Arc::clone(&changed);
+
+            (move |time: Res<Time>| {
+                if time.is_changed() {
+                    changed.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .into_system()
+        };
+
+        let mut world = World::new();
+        world.insert_resource(Time(0.0));
+
+        system.run(&mut world);
+        system.run(&mut world);
+
+        world.resource_mut::<Time>().0 += 1.0;
+        system.run(&mut world);
+
+        assert_eq!(changed.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not exist")]
+    fn test_missing_resource() {
+        let mut system = (|_time: Res<Time>| {}).into_system();
+
+        system.run(&mut World::new());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_resource_conflict() {
+        fn conflict(_a: Res<Time>, _b: ResMut<Time>) {}
+
+        conflict.into_system();
+    }
+
+    #[test]
+    fn test_events() {
+        #[derive(Debug)]
+        struct Collision(u32);
+
+        fn collide(mut writer: EventWriter<Collision>, query: Query<&Velocity>) {
+            writer.send_batch(query.iter().map(|velocity| Collision(velocity.0 as u32)));
+        }
+
+        let seen = Arc::new(AtomicUsize::new(0));
+
+        let reader = || {
+            let seen = Arc::clone(&seen);
+
+            (move |mut reader: EventReader<Collision>| {
+                for collision in reader.read() {
+                    seen.fetch_add(collision.0 as usize, Ordering::Relaxed);
+                }
+            })
+            .into_system()
+        };
+
+        let mut world = World::new();
+
+        world.add_event::<Collision>();
+        world.spawn((Velocity(1.0),));
+        world.spawn((Velocity(2.0),));
+
+        let mut collide = collide.into_system();
+        let (mut a, mut b) = (reader(), reader());
+
+        collide.run(&mut world);
+        a.run(&mut world);
+        world.update_events();
+        b.run(&mut world);
+        b.run(&mut world);
+        world.update_events();
+        a.run(&mut world);
+
+        // Both readers saw both collisions exactly once.
+        assert_eq!(seen.load(Ordering::Relaxed), 6);
+
+        world.update_events();
+        assert!(world.resource::<Events<Collision>>().is_empty());
+    }
+
+    #[test]
+    fn test_commands() {
+        fn split(mut commands: Commands, query: Query<(Entity, &Velocity)>) {
+            for (entity, velocity) in query.iter() {
+                commands.entity(entity).despawn();
+                commands.spawn((Velocity(velocity.0 / 2.0),));
+                commands.spawn((Velocity(velocity.0 / 2.0),));
+            }
+        }
+
+        let mut world = World::new();
+
+        world.spawn((Velocity(4.0),));
+
+        let mut system = split.into_system();
+        system.run(&mut world);
+        system.run(&mut world);
+
+        let velocities = world
+            .query::<&Velocity>()
+            .iter()
+            .map(|v| v.0)
+            .collect::<Vec<_>>();
+        assert_eq!(velocities, [1.0; 4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_conflict() {
+        fn conflict(_a: Query<&mut Position>, _b: Query<&Position>) {}
+
+        conflict.into_system();
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    any::Any,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+type TaskFn<'a> = dyn Fn() + Sync + 'a;
+
+/// Threads that are kept alive between parallel queries, so that each query
+/// doesn't have to spawn its own.
+pub(crate) struct TaskPool {
+    sender: Option<mpsc::Sender<Job>>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+/// How far the calls of one [`TaskPool::broadcast`] have got.
+#[derive(Default)]
+struct Progress {
+    cancelled: bool,
+    running: usize,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+/// The function passed to [`TaskPool::broadcast`], with its lifetime erased.
+struct Task(*const TaskFn<'static>);
+
+// The function is `Sync`, and is only called while `broadcast` waits for it.
+unsafe impl Send for Task {}
+
+impl TaskPool {
+    pub(crate) fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let threads = (0..threads)
+            .map(|_| {
+                let receiver = receiver.clone();
+
+                thread::spawn(move || loop {
+                    // The lock is released at the end of the statement, so
+                    // other threads can pick up jobs while this one runs.
+                    let job = receiver.lock().unwrap().recv();
+
+                    let Ok(job) = job else {
+                        break;
+                    };
+
+                    job();
+                })
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            threads,
+        }
+    }
+
+    pub(crate) fn threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Calls `f` on the current thread and on up to `extra` pool threads at
+    /// once, returning when every call has. Calls that haven't started by the
+    /// time the current thread's returns are skipped, so `f` should take its
+    /// work from a shared queue rather than count on a number of calls. This
+    /// also keeps `broadcast` from waiting on a busy pool when it's called
+    /// from one of the pool's threads.
+    pub(crate) fn broadcast(&self, extra: usize, f: &TaskFn<'_>) {
+        let state = Arc::new((Mutex::new(Progress::default()), Condvar::new()));
+
+        for _ in 0..extra.min(self.threads()) {
+            let state = state.clone();
+            let task =
+                Task(unsafe { mem::transmute::<*const TaskFn<'_>, *const TaskFn<'static>>(f) });
+
+            let job = move || {
+                // Moves the whole `Task` in, rather than just its pointer.
+                let task = task;
+                let (progress, done) = &*state;
+
+                {
+                    let mut progress = progress.lock().unwrap();
+
+                    if progress.cancelled {
+                        return;
+                    }
+
+                    progress.running += 1;
+                }
+
+                // `broadcast` doesn't return while the call is running.
+                let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe { (*task.0)() }));
+
+                let mut progress = progress.lock().unwrap();
+                progress.running -= 1;
+
+                if let Err(payload) = result {
+                    progress.panic.get_or_insert(payload);
+                }
+
+                done.notify_all();
+            };
+
+            self.sender.as_ref().unwrap().send(Box::new(job)).unwrap();
+        }
+
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+
+        let (progress, done) = &*state;
+        let mut progress = progress.lock().unwrap();
+        progress.cancelled = true;
+
+        while progress.running > 0 {
+            progress = done.wait(progress).unwrap();
+        }
+
+        let panic = progress.panic.take();
+        drop(progress);
+
+        if let Err(payload) = result {
+            panic::resume_unwind(payload);
+        }
+
+        if let Some(payload) = panic {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl Default for TaskPool {
+    /// A pool with a thread for each core besides the current one.
+    fn default() -> Self {
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+
+        Self::new(cores - 1)
+    }
+}
+
+impl Drop for TaskPool {
+    fn drop(&mut self) {
+        // Closing the channel stops the threads once they run out of jobs.
+        drop(self.sender.take());
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Calls `f` on every index in `0..len`, each exactly once.
+    fn for_each_index(pool: &TaskPool, len: usize, f: &(dyn Fn(usize) + Sync)) {
+        let next = AtomicUsize::new(0);
+
+        pool.broadcast(len, &|| loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+
+            if index >= len {
+                break;
+            }
+
+            f(index);
+        });
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let pool = TaskPool::new(4);
+        let visits = (0..1000).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+        for _ in 0..10 {
+            for_each_index(&pool, visits.len(), &|index| {
+                visits[index].fetch_add(1, Ordering::Relaxed);
+            });
+        }
+
+        assert!(visits
+            .iter()
+            .all(|visits| visits.load(Ordering::Relaxed) == 10));
+    }
+
+    #[test]
+    fn test_nested() {
+        let pool = TaskPool::new(2);
+        let sum = AtomicUsize::new(0);
+
+        for_each_index(&pool, 10, &|_| {
+            for_each_index(&pool, 10, &|index| {
+                sum.fetch_add(index, Ordering::Relaxed);
+            });
+        });
+
+        assert_eq!(sum.into_inner(), 10 * 45);
+    }
+
+    #[test]
+    #[should_panic(expected = "in a pool thread")]
+    fn test_panic() {
+        let pool = TaskPool::new(2);
+
+        for_each_index(&pool, 100, &|index| {
+            if index == 99 {
+                panic!("in a pool thread");
+            }
+        });
+    }
+}
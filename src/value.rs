@@ -0,0 +1,578 @@
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+use crate::{Entity, SnapshotError};
+
+/// A tree of plain data, the shape components take in a text snapshot.
+///
+/// It's written as JSON, except that floats always have a decimal point or an
+/// exponent, so they read back as floats, and that `NaN`, `inf` and `-inf`
+/// are allowed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    List(Vec<Value>),
+    /// Entries keep the order they were written in.
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Builds a map from `(key, value)` pairs.
+    pub fn map<K: Into<String>>(entries: impl IntoIterator<Item = (K, Value)>) -> Self {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.into(), value))
+                .collect(),
+        )
+    }
+
+    /// Looks up `key` if this is a map.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_map()?
+            .iter()
+            .find_map(|(k, value)| (k == key).then_some(value))
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns floats as they are and converts integers.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Float(value) => Some(value),
+            Value::Int(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Reads an entity written as `[index, generation]`.
+    pub fn as_entity(&self) -> Option<Entity> {
+        match self.as_list()? {
+            [id, generation] => Some(Entity::new(
+                u32::try_from(id.as_i64()?).ok()? as usize,
+                u32::try_from(generation.as_i64()?).ok()?,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Parses a value written by [`Value`]'s `Display` implementation.
+    pub fn parse(text: &str) -> Result<Value, SnapshotError> {
+        let mut parser = Parser {
+            text,
+            chars: text.char_indices().peekable(),
+            depth: 0,
+        };
+
+        let value = parser.value()?;
+        parser.skip_whitespace();
+
+        match parser.chars.peek() {
+            Some(_) => Err(parser.error("expected the end of the text")),
+            None => Ok(value),
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Value::List(_) | Value::Map(_))
+    }
+
+    /// Writes lists and maps of scalars on one line, and everything else with
+    /// one entry per line.
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Int(value) => write!(f, "{value}"),
+            Value::Float(value) => write_float(f, *value),
+            Value::String(value) => write_string(f, value),
+            Value::List(values) => {
+                let inline = values.iter().all(Value::is_scalar);
+
+                write_entries(f, indent, '[', ']', inline, values, |f, value| {
+                    value.write(f, indent + 1)
+                })
+            }
+            Value::Map(entries) => {
+                let inline = entries.iter().all(|(_, value)| value.is_scalar());
+
+                write_entries(f, indent, '{', '}', inline, entries, |f, (key, value)| {
+                    write_string(f, key)?;
+                    f.write_str(": ")?;
+                    value.write(f, indent + 1)
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+fn write_entries<T>(
+    f: &mut fmt::Formatter<'_>,
+    indent: usize,
+    open: char,
+    close: char,
+    inline: bool,
+    entries: &[T],
+    mut write: impl FnMut(&mut fmt::Formatter<'_>, &T) -> fmt::Result,
+) -> fmt::Result {
+    write!(f, "{open}")?;
+
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            f.write_str(",")?;
+        }
+
+        if inline {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+        } else {
+            write!(f, "\n{:width$}", "", width = (indent + 1) * 2)?;
+        }
+
+        write(f, entry)?;
+    }
+
+    if !inline && !entries.is_empty() {
+        write!(f, "\n{:width$}", "", width = indent * 2)?;
+    }
+
+    write!(f, "{close}")
+}
+
+fn write_float(f: &mut fmt::Formatter<'_>, value: f64) -> fmt::Result {
+    if value.is_nan() {
+        f.write_str("NaN")
+    } else if value.is_infinite() {
+        f.write_str(if value > 0.0 { "inf" } else { "-inf" })
+    } else if value.fract() == 0.0 && value.abs() < 1e16 {
+        write!(f, "{value:.1}")
+    } else {
+        write!(f, "{value:?}")
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+
+    f.write_str("\"")
+}
+
+macro_rules! impl_from {
+    ($($ty: ty => $variant: ident as $as: ty),*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(value: $ty) -> Self {
+                    Value::$variant(value as $as)
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(i32 => Int as i64, u32 => Int as i64, i64 => Int as i64, f32 => Float as f64, f64 => Float as f64);
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<Entity> for Value {
+    fn from(entity: Entity) -> Self {
+        Value::List(vec![
+            Value::Int(entity.id() as i64),
+            Value::Int(entity.generation() as i64),
+        ])
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+/// How deeply maps and lists can be nested before parsing fails, so deeply
+/// nested text can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> Result<Value, SnapshotError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('{') => self.map(),
+            Some('[') => self.list(),
+            Some('"') => self.string().map(Value::String),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_alphabetic() => {
+                let word = self.take_while(char::is_alphanumeric);
+
+                match word {
+                    "null" => Ok(Value::Null),
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "NaN" => Ok(Value::Float(f64::NAN)),
+                    "inf" => Ok(Value::Float(f64::INFINITY)),
+                    _ => Err(self.error(&format!("unexpected `{word}`"))),
+                }
+            }
+            Some(c) => Err(self.error(&format!("unexpected `{c}`"))),
+            None => Err(self.error("unexpected end of text")),
+        }
+    }
+
+    fn map(&mut self) -> Result<Value, SnapshotError> {
+        let mut entries = Vec::new();
+
+        self.entries('{', '}', |parser| {
+            parser.skip_whitespace();
+
+            let key = parser.string()?;
+
+            parser.skip_whitespace();
+            parser.expect(':')?;
+
+            entries.push((key, parser.value()?));
+
+            Ok(())
+        })?;
+
+        Ok(Value::Map(entries))
+    }
+
+    fn list(&mut self) -> Result<Value, SnapshotError> {
+        let mut values = Vec::new();
+
+        self.entries('[', ']', |parser| {
+            values.push(parser.value()?);
+
+            Ok(())
+        })?;
+
+        Ok(Value::List(values))
+    }
+
+    /// Parses comma-separated entries between `open` and `close`, allowing a
+    /// trailing comma.
+    fn entries(
+        &mut self,
+        open: char,
+        close: char,
+        mut entry: impl FnMut(&mut Self) -> Result<(), SnapshotError>,
+    ) -> Result<(), SnapshotError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+
+        self.expect(open)?;
+        self.depth += 1;
+
+        loop {
+            self.skip_whitespace();
+
+            if self.peek() == Some(close) {
+                self.chars.next();
+                self.depth -= 1;
+                return Ok(());
+            }
+
+            entry(self)?;
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(',') => {
+                    self.chars.next();
+                }
+                Some(c) if c == close => {}
+                _ => return Err(self.error(&format!("expected `,` or `{close}`"))),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        self.expect('"')?;
+
+        let mut string = String::new();
+
+        loop {
+            let Some((_, c)) = self.chars.next() else {
+                return Err(self.error("unterminated string"));
+            };
+
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = match self.chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+
+                    string.push(escaped);
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, SnapshotError> {
+        let mut code = 0;
+
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, c)| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid unicode escape"))?;
+
+            code = code * 16 + digit;
+        }
+
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn number(&mut self) -> Result<Value, SnapshotError> {
+        let text = self.take_while(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+
+        if text == "-inf" {
+            return Ok(Value::Float(f64::NEG_INFINITY));
+        }
+
+        let value = if text.contains(['.', 'e', 'E']) {
+            text.parse().map(Value::Float).ok()
+        } else {
+            text.parse().map(Value::Int).ok()
+        };
+
+        value.ok_or_else(|| self.error(&format!("invalid number `{text}`")))
+    }
+
+    fn take_while(&mut self, mut f: impl FnMut(char) -> bool) -> &'a str {
+        let start = self.offset();
+
+        while self.peek().is_some_and(&mut f) {
+            self.chars.next();
+        }
+
+        &self.text[start..self.offset()]
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SnapshotError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.chars.next();
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected `{expected}`"))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.chars.next();
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.text.len(), |&(offset, _)| offset)
+    }
+
+    /// Reports `message` at the current line and column, both counted from 1.
+    fn error(&mut self, message: &str) -> SnapshotError {
+        let before = &self.text[..self.offset()];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap().chars().count() + 1;
+
+        SnapshotError::Syntax {
+            line,
+            column,
+            message: message.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let value = Value::map([
+            ("name", Value::from("tank \"A\"\n")),
+            ("health", Value::from(-3)),
+            ("speed", Value::from(2.0)),
+            ("scale", Value::from(1.5e-20)),
+            ("visible", Value::from(true)),
+            ("target", Value::Null),
+            ("owner", Value::from(Entity::new(4, 2))),
+            ("path", Value::from(vec![Value::from(1), Value::from(2.5)])),
+            (
+                "nested",
+                Value::List(vec![Value::map([("x", Value::from(1))])]),
+            ),
+            ("empty", Value::Map(Vec::new())),
+        ]);
+
+        let text = value.to_string();
+
+        assert_eq!(Value::parse(&text), Ok(value));
+    }
+
+    #[test]
+    fn test_entity() {
+        let entity = |text| Value::parse(text).unwrap().as_entity();
+
+        assert_eq!(
+            entity("[4294967295, 3]"),
+            Some(Entity::new(u32::MAX as usize, 3))
+        );
+        assert_eq!(entity("[4294967296, 0]"), None);
+        assert_eq!(entity("[-1, 0]"), None);
+        assert_eq!(entity("[1, 4294967296]"), None);
+    }
+
+    #[test]
+    fn test_format() {
+        let value = Value::map([
+            ("position", Value::map([("x", Value::from(1.0))])),
+            ("tags", Value::from(vec!["a", "b"])),
+        ]);
+
+        assert_eq!(
+            value.to_string(),
+            "{\n  \"position\": {\"x\": 1.0},\n  \"tags\": [\"a\", \"b\"]\n}"
+        );
+    }
+
+    #[test]
+    fn test_floats() {
+        for float in [0.0, -1.0, 0.1, 1e300, f64::INFINITY, f64::NEG_INFINITY] {
+            let text = Value::Float(float).to_string();
+
+            assert_eq!(Value::parse(&text), Ok(Value::Float(float)), "{text}");
+        }
+
+        let nan = Value::parse(&Value::Float(f64::NAN).to_string()).unwrap();
+        assert!(nan.as_f64().unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Value::parse("{\n  \"a\": [1, 2,, 3]\n}"),
+            Err(SnapshotError::Syntax {
+                line: 2,
+                column: 14,
+                message: "unexpected `,`".into()
+            })
+        );
+
+        assert!(Value::parse("[1, 2").is_err());
+        assert!(Value::parse("1 2").is_err());
+        assert!(Value::parse("\"\\x\"").is_err());
+    }
+
+    #[test]
+    fn test_depth() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+        assert!(Value::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Value::parse(&nested(MAX_DEPTH + 1)),
+            Err(SnapshotError::Syntax {
+                line: 1,
+                column: MAX_DEPTH + 1,
+                message: "too deeply nested".into()
+            })
+        );
+        assert!(matches!(
+            Value::parse(&"[".repeat(1_000_000)),
+            Err(SnapshotError::Syntax { .. })
+        ));
+    }
+}
@@ -1,52 +1,486 @@
-use std::any::TypeId;
+use std::{
+    any::{type_name, TypeId},
+    error::Error,
+    fmt,
+    mem::ManuallyDrop,
+    ptr,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
 
 use rustc_hash::FxHashMap;
 
 use crate::{
-    archetype::ComponentInfo, bundle::Bundle, entity_ref::get_component_mut, Archetype,
-    ArchetypeId, Archetypes, Component, Entities, Entity, EntityBuilder, EntityMut, EntityRef,
-    Location,
+    bundle::{self, Bundle},
+    component,
+    entity_ref::get_component_mut,
+    hierarchy, snapshot,
+    task_pool::TaskPool,
+    Ancestors, Archetype, ArchetypeId, Archetypes, Children, Column, CommandQueue, Commands,
+    Component, ComponentConfig, ComponentDescriptor, ComponentHook, ComponentHooks, ComponentId,
+    ComponentRegistry, ComponentTicks, ComponentsQuery, Descendants, Entities, Entity,
+    EntityBuilder, EntityMut, EntityRef, Event, Events, Location, Mut, Parent, Query, QueryFilter,
+    ReadOnlyQuery, Resource, Resources, SnapshotComponent, SnapshotError, SparseSets, StorageType,
+    Tick, TypeInfo, Value, WorldSnapshot,
 };
 
-#[derive(Debug, Default)]
+/// Identifies a world, so that state made for one isn't used with another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorldId(usize);
+
+impl WorldId {
+    fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
 pub struct World {
+    id: WorldId,
     pub archetypes: Archetypes,
     pub entities: Entities,
-    pub _components: FxHashMap<TypeId, FxHashMap<Archetypes, ComponentInfo>>,
+    components: ComponentRegistry,
+    pub(crate) resources: Resources,
+    pub(crate) sparse_sets: SparseSets,
+    hooks: FxHashMap<TypeId, ComponentHooks>,
+    event_updates: Vec<fn(&mut World)>,
+    change_tick: AtomicU32,
+    last_change_tick: Tick,
+    task_pool: OnceLock<TaskPool>,
+}
+
+impl fmt::Debug for World {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("World")
+            .field("id", &self.id)
+            .field("components", &self.components)
+            .field("archetypes", &self.archetypes)
+            .field("sparse_sets", &self.sparse_sets)
+            .field("entities", &self.entities)
+            .field("resources", &self.resources)
+            .field("change_tick", &self.change_tick())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
         Self {
+            id: WorldId::new(),
             archetypes: Archetypes::default(),
             entities: Default::default(),
-            _components: Default::default(),
+            components: ComponentRegistry::new(),
+            resources: Resources::default(),
+            sparse_sets: SparseSets::default(),
+            hooks: FxHashMap::default(),
+            event_updates: Vec::new(),
+            change_tick: AtomicU32::new(1),
+            last_change_tick: Tick::new(0),
+            task_pool: OnceLock::new(),
         }
     }
 
+    pub fn id(&self) -> WorldId {
+        self.id
+    }
+
+    /// The threads parallel queries run on, started the first time one runs.
+    pub(crate) fn task_pool(&self) -> &TaskPool {
+        self.task_pool.get_or_init(TaskPool::default)
+    }
+
+    /// Replaces the task pool, so that tests can use more threads than the
+    /// machine has cores.
+    #[cfg(test)]
+    pub(crate) fn set_task_pool(&mut self, pool: TaskPool) {
+        self.task_pool = OnceLock::from(pool);
+    }
+
+    /// The tick that changes made right now are stamped with.
+    pub fn change_tick(&self) -> Tick {
+        Tick::new(self.change_tick.load(Ordering::Acquire))
+    }
+
+    /// The tick [`World::clear_trackers`] was last called at. Queries run
+    /// straight on the world report changes made since then.
+    pub fn last_change_tick(&self) -> Tick {
+        self.last_change_tick
+    }
+
+    /// Advances the change tick, returning its previous value.
+    pub fn increment_change_tick(&self) -> Tick {
+        Tick::new(self.change_tick.fetch_add(1, Ordering::AcqRel))
+    }
+
+    /// Marks everything added or changed so far as seen, so that `Added` and
+    /// `Changed` filters on world queries only match later changes.
+    pub fn clear_trackers(&mut self) {
+        self.last_change_tick = self.increment_change_tick();
+    }
+
     pub fn spawn<B: Bundle>(&mut self, b: B) -> Entity {
+        self.flush();
+
+        // Checked before the entity is reserved, so a panic doesn't leak it.
+        bundle::assert_unique(&B::type_info());
+
         let entity = self.entities.reserve_entity();
+        self.spawn_at(entity, b);
 
-        let mut types = b.types();
-        let components = b.components().into_vec();
+        entity
+    }
 
-        let archetype = self.get_archetype_mut(&mut types);
+    /// Reserves an entity through a shared reference, for example to refer to
+    /// it in commands before it's spawned. It has no components until then.
+    pub fn reserve_entity(&self) -> Entity {
+        self.entities.reserve_entity_atomic()
+    }
 
-        for (component, id) in components.into_iter().zip(types.iter()) {
-            let info = archetype.get_component_index(id).unwrap();
+    /// Places the entities reserved with [`World::reserve_entity`] in the
+    /// archetype with no components, so that they can be used like any other.
+    pub fn flush(&mut self) {
+        if !self.entities.needs_flush() {
+            return;
+        }
+
+        let id = self.archetypes.get_or_create(Vec::new());
+        let archetype = self.archetypes.get_by_id_mut(id);
+
+        self.entities.flush(|entity| {
+            archetype.entities.push(entity);
+
+            Location {
+                row: archetype.row(),
+                id,
+            }
+        });
+    }
 
-            archetype.components[info.column].push(component);
+    /// Applies the commands in `queue` in the order they were recorded.
+    pub fn apply_commands(&mut self, queue: &mut CommandQueue) {
+        queue.apply(self);
+    }
+
+    /// Gives a reserved entity the components in `b`. The entity must not have
+    /// any components yet.
+    pub(crate) fn spawn_at<B: Bundle>(&mut self, entity: Entity, b: B) {
+        if let Some(location) = self.entities.get(entity) {
+            let archetype = self.archetypes.get_by_id_mut(location.id);
+            debug_assert!(archetype.components.is_empty());
+
+            if let Some(moved) = archetype.swap_remove(location.row) {
+                self.entities.set_location(moved, location);
+            }
         }
 
-        let id = archetype.id();
+        let ticks = ComponentTicks::new(self.change_tick());
+        let infos = self.table_infos(B::type_info());
+        let id = self.archetypes.get_or_create(infos);
+        let archetype = self.archetypes.get_by_id_mut(id);
+        let sparse_sets = &mut self.sparse_sets;
+
+        unsafe {
+            b.put(&mut |ptr, info| match sparse_sets.get_mut(info.id()) {
+                Some(set) => set.insert(entity, ptr, ticks),
+                None => archetype.put(info.id(), ptr, ticks),
+            })
+        };
+        archetype.entities.push(entity);
+
         let row = archetype.row();
 
-        self.entities.entities[entity.id()] = Location { row, id };
+        self.entities.set_location(entity, Location { row, id });
 
-        entity
+        if !self.hooks.is_empty() {
+            let types = B::type_info().iter().map(TypeInfo::id).collect::<Vec<_>>();
+            self.on_added(entity, &types);
+        }
+    }
+
+    /// Chooses how components of type `T` are stored. Components are stored in
+    /// tables unless registered otherwise, and the choice can only be made
+    /// before any archetype or entity uses `T`.
+    pub fn register_component<T: Component>(&mut self, storage: StorageType) {
+        let type_id = TypeId::of::<T>();
+
+        let in_use = self
+            .archetypes
+            .archetypes
+            .iter()
+            .any(Archetype::has_component::<T>)
+            || self
+                .sparse_sets
+                .get(type_id)
+                .is_some_and(|set| !set.is_empty());
+
+        assert!(
+            !in_use,
+            "component `{}` is already in use",
+            type_name::<T>()
+        );
+
+        self.set_storage_type(&TypeInfo::of::<T>(), storage);
+    }
+
+    /// Registers the type and moves its storage, which must be empty.
+    pub(crate) fn set_storage_type(&mut self, info: &TypeInfo, storage: StorageType) {
+        let id = self.register_info(info);
+        self.components.set_storage_type(id, storage);
+
+        match storage {
+            StorageType::Table => {
+                self.sparse_sets.remove(info.id());
+            }
+            StorageType::SparseSet => {
+                self.sparse_sets.get_or_insert(*info);
+            }
+        }
+    }
+
+    /// Applies the storage type and hooks of `T` to every world that doesn't
+    /// use `T` yet, including this one, in which it must not be in use. Types
+    /// that derive `Component` don't need this.
+    pub fn init_component<T: ComponentConfig>(&mut self) -> ComponentId {
+        if component::register_config::<T>() {
+            assert!(
+                self.components.get_id(TypeId::of::<T>()).is_none(),
+                "component `{}` is already in use",
+                type_name::<T>()
+            );
+        }
+
+        self.register_info(&TypeInfo::of::<T>())
+    }
+
+    /// Registers the type, applying its [`ComponentConfig`] if the world
+    /// hasn't seen it before.
+    pub(crate) fn register_info(&mut self, info: &TypeInfo) -> ComponentId {
+        if let Some(id) = self.components.get_id(info.id()) {
+            return id;
+        }
+
+        let id = self.components.register_info(info);
+
+        if let Some(config) = component::config(info.id()) {
+            self.set_storage_type(info, config.storage);
+            (config.register_hooks)(self.hooks.entry(info.id()).or_default());
+        }
+
+        id
+    }
+
+    pub fn storage_type<T: Component>(&self) -> StorageType {
+        if self.sparse_sets.contains(TypeId::of::<T>()) {
+            StorageType::SparseSet
+        } else {
+            StorageType::Table
+        }
     }
 
-    pub fn create_entity(&mut self) -> EntityBuilder {
+    /// Every component type the world has stored or had registered.
+    pub fn components(&self) -> &ComponentRegistry {
+        &self.components
+    }
+
+    /// Registers `T` with a function that clones it, for code that clones
+    /// components without knowing their types.
+    pub fn register_clone<T: Component + Clone>(&mut self) -> ComponentId {
+        self.register_info(&TypeInfo::of::<T>());
+        self.components.register_clone::<T>()
+    }
+
+    /// Registers `T` with a function that creates its default value.
+    pub fn register_default<T: Component + Default>(&mut self) -> ComponentId {
+        self.register_info(&TypeInfo::of::<T>());
+        self.components.register_default::<T>()
+    }
+
+    /// Registers `T` with functions that write it to snapshots and read it
+    /// back. Only registered components are saved by
+    /// [`World::save_snapshot`].
+    pub fn register_snapshot<T: SnapshotComponent>(&mut self) -> ComponentId {
+        self.register_info(&TypeInfo::of::<T>());
+        self.components.register_snapshot::<T>()
+    }
+
+    /// Writes every entity and its registered components as text that
+    /// [`World::load_snapshot`] reads back. Resources aren't saved.
+    pub fn save_snapshot(&self) -> String {
+        snapshot::save(self).to_string()
+    }
+
+    /// Loads a snapshot into a world that has never had entities, restoring
+    /// each entity in the same archetype and row, and with the same
+    /// generation, as when it was saved. Every component must be stored the
+    /// same way as in the saved world. Hooks don't run. On error, the world is
+    /// left as it was.
+    pub fn load_snapshot(&mut self, text: &str) -> Result<(), SnapshotError> {
+        snapshot::load(self, &Value::parse(text)?)
+    }
+
+    /// Registers `T` as plain data, which [`WorldSnapshot`] copies as bytes.
+    pub fn register_pod<T: Component + Copy>(&mut self) -> ComponentId {
+        self.register_info(&TypeInfo::of::<T>());
+        self.components.register_pod::<T>()
+    }
+
+    /// Takes a binary snapshot of every entity and its components, which must
+    /// all be registered with [`World::register_pod`]. Use
+    /// [`WorldSnapshot::capture`] to reuse an existing snapshot instead.
+    pub fn snapshot(&self) -> Result<WorldSnapshot, SnapshotError> {
+        let mut snapshot = WorldSnapshot::new();
+        snapshot.capture(self)?;
+
+        Ok(snapshot)
+    }
+
+    /// Puts the world back in the state `snapshot` was taken in, in place.
+    /// Resources and hooks are left alone. Panics if the snapshot was taken
+    /// from a different world.
+    pub fn restore_snapshot(&mut self, snapshot: &WorldSnapshot) {
+        snapshot.restore(self);
+    }
+
+    /// Creates an independent copy of the world, in which every entity handle
+    /// refers to the same entity with copies of the same components.
+    ///
+    /// Components are copied with the functions registered through
+    /// [`World::register_clone`], or as bytes for types registered with
+    /// [`World::register_pod`]. Fails, naming the type, if any other component
+    /// has values. Hooks are copied, but resources and events are not.
+    pub fn clone_world(&self) -> Result<World, CloneError> {
+        let in_use = |type_id| {
+            self.archetypes.archetypes.iter().any(|archetype| {
+                archetype
+                    .get_component_index(&type_id)
+                    .is_some_and(|_| !archetype.is_empty())
+            }) || self
+                .sparse_sets
+                .get(type_id)
+                .is_some_and(|set| !set.is_empty())
+        };
+
+        let unclonable = self.components.iter().find(|descriptor| {
+            descriptor.clone_fn().is_none() && !descriptor.is_pod() && in_use(descriptor.type_id())
+        });
+
+        if let Some(descriptor) = unclonable {
+            return Err(CloneError::NotClonable(descriptor.type_name()));
+        }
+
+        // Every column with rows has a clone function or holds `Copy` values.
+        let clone_column = |column: &Column| unsafe {
+            let clone = self
+                .components
+                .get_by_type_id(column.info().id())
+                .and_then(ComponentDescriptor::clone_fn);
+
+            column.clone_with(clone)
+        };
+
+        Ok(World {
+            id: WorldId::new(),
+            archetypes: self.archetypes.clone_with(clone_column),
+            entities: self.entities.clone(),
+            components: self.components.clone(),
+            resources: Resources::default(),
+            sparse_sets: self.sparse_sets.clone_with(clone_column),
+            hooks: self.hooks.clone(),
+            event_updates: Vec::new(),
+            change_tick: AtomicU32::new(self.change_tick.load(Ordering::Acquire)),
+            last_change_tick: self.last_change_tick,
+            task_pool: OnceLock::new(),
+        })
+    }
+
+    /// Registers every type in `infos`, then leaves out the ones stored in
+    /// sparse sets, which archetypes have no columns for.
+    pub(crate) fn table_infos(&mut self, infos: Vec<TypeInfo>) -> Vec<TypeInfo> {
+        for info in &infos {
+            self.register_info(info);
+        }
+
+        infos
+            .into_iter()
+            .filter(|info| !self.sparse_sets.contains(info.id()))
+            .collect()
+    }
+
+    /// Returns the hooks for the component `T`, to register new ones.
+    pub fn register_component_hooks<T: Component>(&mut self) -> &mut ComponentHooks {
+        self.hooks
+            .entry(TypeId::of::<T>())
+            .or_insert_with(ComponentHooks::of::<T>)
+    }
+
+    /// Runs the hook `select` picks for each of `types` that has one, queueing
+    /// the commands they record in `queue`.
+    fn run_hooks(
+        &self,
+        entity: Entity,
+        types: &[TypeId],
+        select: fn(&ComponentHooks) -> Option<ComponentHook>,
+        queue: &mut CommandQueue,
+    ) {
+        if self.hooks.is_empty() {
+            return;
+        }
+
+        let mut commands = Commands::new(queue, self);
+
+        for type_id in types {
+            if let Some(hook) = self.hooks.get(type_id).and_then(select) {
+                hook(self, entity, &mut commands);
+            }
+        }
+    }
+
+    /// Runs the `on_remove` hooks for components the entity is about to lose,
+    /// then takes it out of the hierarchy if it loses [`Parent`] or
+    /// [`Children`].
+    fn run_remove_hooks(&self, entity: Entity, types: &[TypeId], queue: &mut CommandQueue) {
+        self.run_hooks(entity, types, |hooks| hooks.on_remove, queue);
+
+        for &type_id in types {
+            if type_id == TypeId::of::<Parent>() {
+                hierarchy::on_remove_parent(self, entity, &mut Commands::new(queue, self));
+            } else if type_id == TypeId::of::<Children>() {
+                hierarchy::on_remove_children(self, entity, &mut Commands::new(queue, self));
+            }
+        }
+    }
+
+    /// Runs the `on_add` and then the `on_insert` hooks for components the
+    /// entity just got, and applies the commands they record.
+    pub(crate) fn on_added(&mut self, entity: Entity, types: &[TypeId]) {
+        let mut queue = CommandQueue::new();
+
+        self.run_hooks(entity, types, |hooks| hooks.on_add, &mut queue);
+        self.run_hooks(entity, types, |hooks| hooks.on_insert, &mut queue);
+
+        self.apply_commands(&mut queue);
+    }
+
+    /// Runs the `on_insert` hooks for a component whose value was replaced,
+    /// and applies the commands they record.
+    fn on_replaced(&mut self, entity: Entity, type_id: TypeId) {
+        let mut queue = CommandQueue::new();
+        self.run_hooks(entity, &[type_id], |hooks| hooks.on_insert, &mut queue);
+        self.apply_commands(&mut queue);
+    }
+
+    pub fn create_entity(&mut self) -> EntityBuilder<'_> {
+        self.flush();
+
         EntityBuilder {
             entity: self.entities.reserve_entity(),
             world: self,
@@ -54,20 +488,141 @@ impl World {
         }
     }
 
-    pub fn remove_entity(&mut self, entity: Entity) {
-        let _location = self.entities.free(entity);
+    /// Removes the entity and drops its components. Returns `false` if the
+    /// entity had already been removed.
+    pub fn remove_entity(&mut self, entity: Entity) -> bool {
+        self.flush();
+
+        let mut queue = CommandQueue::new();
+
+        if let Some(location) = self.entities.get(entity) {
+            let sparse = self
+                .sparse_sets
+                .iter()
+                .filter(|set| set.contains(entity))
+                .map(|set| set.info().id());
+
+            let types = self.archetypes.get_by_id(location.id).types.iter().copied();
+            let types = types.chain(sparse).collect::<Vec<_>>();
+
+            self.run_remove_hooks(entity, &types, &mut queue);
+        }
+
+        // The hooks may have reserved entities, which have to be placed before
+        // the index is freed.
+        self.flush();
+
+        let Some(location) = self.entities.free(entity) else {
+            return false;
+        };
+
+        if location.id != ArchetypeId::INVALID {
+            let archetype = self.archetypes.get_by_id_mut(location.id);
+
+            if let Some(moved) = archetype.swap_remove(location.row) {
+                self.entities.set_location(moved, location);
+            }
+        }
+
+        for set in self.sparse_sets.iter_mut() {
+            set.remove(entity);
+        }
+
+        self.apply_commands(&mut queue);
+
+        true
     }
 
-    pub fn get_archetype_mut(&mut self, types: &mut Box<[TypeId]>) -> &mut Archetype {
-        if !self.archetypes.has_archetype(types) {
-            self.create_archetype(types);
+    /// Makes `child` a child of `parent`, taking it out of the children of its
+    /// old parent if it had one. Panics if `parent` doesn't exist or is
+    /// `child` itself or one of its descendants.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) {
+        assert!(
+            self.get_entity(parent).is_some(),
+            "parent {:?} doesn't exist",
+            parent
+        );
+        assert!(
+            child != parent && !self.ancestors(parent).any(|ancestor| ancestor == child),
+            "{:?} cannot be a child of itself or of its descendant {:?}",
+            child,
+            parent
+        );
+
+        if self.get::<Parent>(child) == Some(&Parent(parent)) {
+            return;
         }
 
-        self.archetypes.get_by_types_mut(types).unwrap()
+        self.remove_parent(child);
+        self.add_component(child, Parent(parent));
+
+        match self.get_mut::<Children>(parent) {
+            Some(mut children) => children.0.push(child),
+            None => self.add_component(parent, Children(vec![child])),
+        }
     }
 
-    pub fn create_archetype(&mut self, types: &mut Box<[TypeId]>) -> ArchetypeId {
-        self.archetypes.create_archetype(types)
+    /// Detaches `child` from its parent, returning the parent it had.
+    pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        // Removing `Parent` takes the child out of the parent's children.
+        self.remove_component::<Parent>(child)
+            .map(|parent| parent.get())
+    }
+
+    /// Takes `child` out of the children of `parent`, removing the parent's
+    /// `Children` once it has none left.
+    pub(crate) fn remove_child(&mut self, parent: Entity, child: Entity) {
+        let Some(mut children) = self.get_mut::<Children>(parent) else {
+            return;
+        };
+
+        children.0.retain(|&other| other != child);
+
+        if children.is_empty() {
+            self.remove_component::<Children>(parent);
+        }
+    }
+
+    /// Removes the entity along with all of its descendants. Returns `false`
+    /// if the entity had already been removed.
+    pub fn despawn_recursive(&mut self, entity: Entity) -> bool {
+        let descendants = self.descendants(entity).collect::<Vec<_>>();
+
+        // Removing the deepest entities first leaves no links to fix up but
+        // the ones to the entity's own parent.
+        for descendant in descendants.into_iter().rev() {
+            self.remove_entity(descendant);
+        }
+
+        self.remove_entity(entity)
+    }
+
+    /// Iterates the parent of `entity`, then its grandparent, and so on.
+    pub fn ancestors(&self, entity: Entity) -> Ancestors<'_> {
+        Ancestors::new(self, entity)
+    }
+
+    /// Iterates every descendant of `entity` depth-first.
+    pub fn descendants(&self, entity: Entity) -> Descendants<'_> {
+        Descendants::new(self, entity)
+    }
+
+    pub fn get_archetype_mut(&mut self, infos: Vec<TypeInfo>) -> &mut Archetype {
+        for info in &infos {
+            self.register_info(info);
+        }
+
+        let id = self.archetypes.get_or_create(infos);
+
+        self.archetypes.get_by_id_mut(id)
+    }
+
+    pub fn create_archetype(&mut self, infos: Vec<TypeInfo>) -> ArchetypeId {
+        for info in &infos {
+            self.register_info(info);
+        }
+
+        self.archetypes.create_archetype(infos)
     }
 
     pub fn entity(&self, entity: Entity) -> EntityRef<'_> {
@@ -76,6 +631,8 @@ impl World {
     }
 
     pub fn entity_mut(&mut self, entity: Entity) -> EntityMut<'_> {
+        self.flush();
+
         let location = self.entities.get(entity).unwrap();
         EntityMut::new(self, entity, location)
     }
@@ -86,130 +643,468 @@ impl World {
     }
 
     pub fn get_entity_mut(&mut self, entity: Entity) -> Option<EntityMut<'_>> {
+        self.flush();
+
         let location = self.entities.get(entity)?;
         Some(EntityMut::new(self, entity, location))
     }
 
     pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
-        self.entity(entity).get::<T>()
+        self.get_entity(entity)?.get::<T>()
     }
 
-    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&'_ mut T> {
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<Mut<'_, T>> {
+        self.flush();
+
         get_component_mut(self, entity, self.entities.get(entity)?)
     }
 
-    // TODO: This is clearly a performance nightmare and a dreadful hack
     pub fn add_component<T: Component>(&mut self, entity: Entity, c: T) {
+        self.flush();
+
         let location = self.entities.get(entity).unwrap();
+        let type_id = TypeId::of::<T>();
+        let ticks = ComponentTicks::new(self.change_tick());
+
+        self.register_info(&TypeInfo::of::<T>());
+
+        if let Some(set) = self.sparse_sets.get_mut(type_id) {
+            let replaced = set.contains(entity);
+            let c = ManuallyDrop::new(c);
+
+            unsafe { set.insert(entity, &*c as *const T as *const u8, ticks) };
+
+            if replaced {
+                self.on_replaced(entity, type_id);
+            } else {
+                self.on_added(entity, &[type_id]);
+            }
+
+            return;
+        }
 
         let old_archetype = self.archetypes.get_by_id_mut(location.id);
 
-        let mut new_type = old_archetype.types.to_vec();
-        new_type.push(TypeId::of::<T>());
-        new_type.sort();
+        if old_archetype.has_component::<T>() {
+            *get_component_mut(self, entity, location).unwrap() = c;
+            self.on_replaced(entity, type_id);
+
+            return;
+        }
+
+        let new_archetype_id = match old_archetype.edges().get(&type_id).and_then(|e| e.add) {
+            Some(id) => id,
+            None => {
+                let mut infos = old_archetype
+                    .components
+                    .iter()
+                    .map(|column| *column.info())
+                    .collect::<Vec<_>>();
+                infos.push(TypeInfo::of::<T>());
 
-        //let index = location.row;
-        let mut components = Vec::new();
+                let id = self.archetypes.get_or_create(infos);
+                self.archetypes.link(location.id, id, type_id);
 
-        for c in old_archetype.components.iter_mut() {
-            components.push(c.remove(location.row));
+                id
+            }
+        };
+
+        let new_location = self.move_entity(location, new_archetype_id, |_, _| unreachable!());
+
+        let c = ManuallyDrop::new(c);
+        unsafe {
+            self.archetypes.get_by_id_mut(new_archetype_id).put(
+                type_id,
+                &*c as *const T as *const u8,
+                ticks,
+            );
+        }
+
+        self.entities.set_location(entity, new_location);
+
+        self.on_added(entity, &[type_id]);
+    }
+
+    /// Removes the component `T` from the entity, moving it into the archetype
+    /// without `T` unless `T` is stored in a sparse set. Returns `None` if the
+    /// entity doesn't have the component.
+    pub fn remove_component<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        self.flush();
+
+        let location = self.entities.get(entity)?;
+        let type_id = TypeId::of::<T>();
+
+        if let Some(set) = self.sparse_sets.get(type_id) {
+            if !set.contains(entity) {
+                return None;
+            }
+
+            let mut queue = CommandQueue::new();
+            self.run_remove_hooks(entity, &[type_id], &mut queue);
+
+            let mut removed = None;
+            unsafe {
+                self.sparse_sets
+                    .get_mut(type_id)
+                    .unwrap()
+                    .remove_with(entity, |ptr| removed = Some(ptr::read(ptr as *const T)));
+            }
+
+            self.apply_commands(&mut queue);
+
+            return removed;
+        }
+
+        let old_archetype = self.archetypes.get_by_id(location.id);
+
+        if !old_archetype.has_component::<T>() {
+            return None;
         }
 
-        components.push(Box::new(c));
+        let new_archetype_id = match old_archetype.edges().get(&type_id).and_then(|e| e.remove) {
+            Some(id) => id,
+            None => {
+                let infos = old_archetype
+                    .components
+                    .iter()
+                    .map(|column| *column.info())
+                    .filter(|info| info.id() != type_id)
+                    .collect();
+
+                let id = self.archetypes.get_or_create(infos);
+                self.archetypes.link(id, location.id, type_id);
+
+                id
+            }
+        };
+
+        let mut queue = CommandQueue::new();
+        self.run_remove_hooks(entity, &[type_id], &mut queue);
+
+        let mut removed = None;
+        let new_location = self.move_entity(location, new_archetype_id, |_, ptr| {
+            removed = Some(unsafe { ptr::read(ptr as *const T) });
+        });
+
+        self.entities.set_location(entity, new_location);
+        self.apply_commands(&mut queue);
+
+        removed
+    }
+
+    /// Inserts every component in `b` into the entity, moving it at most once,
+    /// straight into the archetype that has all of them. Components the
+    /// entity already has are replaced. Panics if the entity doesn't exist or
+    /// a component type appears in `b` more than once.
+    pub fn insert_bundle<B: Bundle>(&mut self, entity: Entity, b: B) {
+        self.flush();
+
+        let infos = B::type_info();
+        bundle::assert_unique(&infos);
+
+        let location = self.entities.get(entity).unwrap();
+        let bundle_id = TypeId::of::<B>();
+        let ticks = ComponentTicks::new(self.change_tick());
+
+        let types = infos.iter().map(TypeInfo::id).collect::<Vec<_>>();
+        let old_archetype = self.archetypes.get_by_id(location.id);
+
+        let (added, replaced): (Vec<_>, Vec<_>) =
+            types
+                .iter()
+                .copied()
+                .partition(|&type_id| match self.sparse_sets.get(type_id) {
+                    Some(set) => !set.contains(entity),
+                    None => !old_archetype.types.contains(&type_id),
+                });
+
+        let cached = old_archetype.bundle_edges().get(&bundle_id);
+
+        let target = match cached.and_then(|edge| edge.add) {
+            Some(id) => id,
+            None => {
+                let old_infos = old_archetype.components.iter().map(|column| *column.info());
+                let new_infos = infos
+                    .into_iter()
+                    .filter(|info| !old_archetype.types.contains(&info.id()));
+
+                let infos = old_infos.chain(new_infos).collect();
+                let infos = self.table_infos(infos);
+                let id = self.archetypes.get_or_create(infos);
 
-        let new_archetype_id = if old_archetype.edges().contains_key(&TypeId::of::<T>()) {
-            old_archetype.edges()[&TypeId::of::<T>()]
+                self.archetypes
+                    .get_by_id_mut(location.id)
+                    .bundle_edges_mut()
+                    .entry(bundle_id)
+                    .or_default()
+                    .add = Some(id);
+
+                id
+            }
+        };
+
+        let new_location = if target == location.id {
+            location
         } else {
-            self.create_archetype(&mut new_type.into_boxed_slice())
+            self.move_entity(location, target, |_, _| unreachable!())
         };
 
-        self.archetypes
-            .get_by_id_mut(location.id)
-            .edges_mut()
-            .insert(TypeId::of::<T>(), new_archetype_id);
+        let archetype = self.archetypes.get_by_id_mut(target);
+        let sparse_sets = &mut self.sparse_sets;
+
+        unsafe {
+            b.put(&mut |ptr, info| match sparse_sets.get_mut(info.id()) {
+                Some(set) => set.insert(entity, ptr, ticks),
+                None if replaced.contains(&info.id()) => {
+                    archetype.replace(info.id(), new_location.row, ptr, ticks.changed)
+                }
+                None => archetype.put(info.id(), ptr, ticks),
+            })
+        };
+
+        self.entities.set_location(entity, new_location);
+
+        let mut queue = CommandQueue::new();
+
+        self.run_hooks(entity, &added, |hooks| hooks.on_add, &mut queue);
+        self.run_hooks(entity, &types, |hooks| hooks.on_insert, &mut queue);
 
-        let new_archetype = self.archetypes.get_by_id_mut(new_archetype_id);
+        self.apply_commands(&mut queue);
+    }
+
+    /// Removes every component in `B` from the entity, moving it at most
+    /// once, and returns them. Returns `None`, leaving the entity as it was,
+    /// if the entity doesn't have all of them.
+    pub fn remove_bundle<B: Bundle>(&mut self, entity: Entity) -> Option<B> {
+        self.flush();
+
+        let location = self.entities.get(entity)?;
+        let bundle_id = TypeId::of::<B>();
+
+        let infos = B::type_info();
+        let types = infos.iter().map(TypeInfo::id).collect::<Vec<_>>();
+        let old_archetype = self.archetypes.get_by_id(location.id);
 
-        for c in new_archetype.components.iter_mut() {
-            c.push(components.pop().unwrap());
+        let has_all = types
+            .iter()
+            .all(|&type_id| match self.sparse_sets.get(type_id) {
+                Some(set) => set.contains(entity),
+                None => old_archetype.types.contains(&type_id),
+            });
+
+        if !has_all {
+            return None;
         }
 
-        let id = new_archetype.id();
+        let cached = old_archetype.bundle_edges().get(&bundle_id);
+
+        let target = match cached.and_then(|edge| edge.remove) {
+            Some(id) => id,
+            None => {
+                // Taking the same component twice would move it out twice.
+                let duplicate = (1..types.len()).find(|&i| types[..i].contains(&types[i]));
+
+                if let Some(i) = duplicate {
+                    panic!(
+                        "component `{}` appears more than once",
+                        infos[i].type_name()
+                    );
+                }
+
+                let infos = old_archetype
+                    .components
+                    .iter()
+                    .map(|column| *column.info())
+                    .filter(|info| !types.contains(&info.id()))
+                    .collect();
+
+                let id = self.archetypes.get_or_create(infos);
+
+                self.archetypes
+                    .get_by_id_mut(location.id)
+                    .bundle_edges_mut()
+                    .entry(bundle_id)
+                    .or_default()
+                    .remove = Some(id);
+
+                id
+            }
+        };
+
+        let mut queue = CommandQueue::new();
+        self.run_remove_hooks(entity, &types, &mut queue);
+
+        let archetype = self.archetypes.get_by_id(location.id);
+        let sparse_sets = &self.sparse_sets;
+
+        // The values are moved out here, then forgotten as the sparse sets let
+        // go of them and the row moves below.
+        let bundle = unsafe {
+            B::take(&mut |info| match sparse_sets.get(info.id()) {
+                Some(set) => set.column().get_unchecked(set.index_of(entity).unwrap()),
+                None => archetype.get_ptr(info.id(), location.row),
+            })
+        };
+
+        for type_id in &types {
+            if let Some(set) = self.sparse_sets.get_mut(*type_id) {
+                unsafe { set.remove_with(entity, |_| {}) };
+            }
+        }
+
+        if target != location.id {
+            let new_location = self.move_entity(location, target, |_, _| {});
+            self.entities.set_location(entity, new_location);
+        }
+
+        self.apply_commands(&mut queue);
+
+        Some(bundle)
+    }
+
+    /// Moves the row at `location` into the `target` archetype, fixing up the
+    /// location of the entity that was swapped into its old row. Components
+    /// `target` has no column for are passed to `f`, and the caller is left to
+    /// fill the columns only `target` has and to update the moved entity's own
+    /// location.
+    fn move_entity(
+        &mut self,
+        location: Location,
+        target: ArchetypeId,
+        f: impl FnMut(&TypeInfo, *mut u8),
+    ) -> Location {
+        let (old_archetype, new_archetype) = self.archetypes.get_pair_mut(location.id, target);
+
+        let moved = unsafe { old_archetype.move_row(location.row, new_archetype, f) };
         let row = new_archetype.row();
 
-        self.entities.entities[entity.id()] = Location { row, id };
+        if let Some(moved) = moved {
+            self.entities.set_location(moved, location);
+        }
+
+        Location { row, id: target }
     }
 
-    pub fn remove_component<T: Component>(&mut self, entity: Entity) {
-        //self.archetypes.get_by_id()
-        let _location = self.entities.get(entity).unwrap();
+    /// Inserts a resource, replacing and returning the one of the same type if
+    /// there was one.
+    pub fn insert_resource<R: Resource>(&mut self, value: R) -> Option<R> {
+        let tick = self.change_tick();
+        self.resources.insert(value, tick)
+    }
 
-        /*let old_archetype = self.archetypes.get_by_id_mut(location.id);
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        self.resources.remove::<R>()
+    }
 
-        let mut new_type = old_archetype.types.to_vec();
-        let index = new_type.iter().position(|x| x == &TypeId::of::<T>()).unwrap();
+    pub fn contains_resource<R: Resource>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+
+    pub fn resource<R: Resource>(&self) -> &R {
+        self.get_resource()
+            .unwrap_or_else(|| panic!("resource `{}` does not exist", type_name::<R>()))
+    }
 
-        new_type.remove(index);
+    pub fn resource_mut<R: Resource>(&mut self) -> Mut<'_, R> {
+        self.get_resource_mut()
+            .unwrap_or_else(|| panic!("resource `{}` does not exist", type_name::<R>()))
+    }
 
-        //new_type.(TypeId::of::<T>());
-        new_type.sort();
+    pub fn get_resource<R: Resource>(&self) -> Option<&R> {
+        self.resources.get::<R>()
+    }
 
-        let index = location.row;
-        let mut components = Vec::new();
+    pub fn get_resource_mut<R: Resource>(&mut self) -> Option<Mut<'_, R>> {
+        let this_run = self.change_tick();
+        self.resources.get_mut::<R>(this_run)
+    }
 
-        for c in old_archetype.components.iter_mut() {
-            let value = c.remove(index);
-            components.push(value);
+    /// Adds an [`Events`] resource for `T`, which [`World::update_events`]
+    /// then updates. Does nothing if `T` was already added.
+    pub fn add_event<T: Event>(&mut self) {
+        if self.contains_resource::<Events<T>>() {
+            return;
         }
 
-        let new_archetype = self.get_archetype_mut(&mut new_type.into_boxed_slice());
+        self.insert_resource(Events::<T>::new());
+        self.event_updates
+            .push(|world| world.resource_mut::<Events<T>>().update());
+    }
+
+    pub fn send_event<T: Event>(&mut self, event: T) {
+        self.get_resource_mut::<Events<T>>()
+            .unwrap_or_else(|| panic!("events `{}` were never added", type_name::<T>()))
+            .send(event);
+    }
 
-        for c in new_archetype.components.iter_mut() {
-            c.push(components.pop().unwrap());
+    /// Updates every event type added with [`World::add_event`], dropping the
+    /// events sent two updates ago. Meant to be called once per frame.
+    pub fn update_events(&mut self) {
+        for update in self.event_updates.clone() {
+            update(self);
         }
+    }
 
-        let id = new_archetype.id();
-        let row = new_archetype.row();
+    pub fn query<Q: ReadOnlyQuery>(&self) -> Query<'_, 'static, Q> {
+        self.query_filtered()
+    }
 
-        self.entities.entities[entity.id()] = Location { row, id };*/
-        todo!()
+    pub fn query_mut<Q: ComponentsQuery>(&mut self) -> Query<'_, 'static, Q> {
+        self.query_filtered_mut()
     }
 
-    pub fn query<T: Component>(&self) -> Vec<&T> {
-        self.archetypes
-            .archetypes
-            .iter()
-            .filter(|a| a.has_component::<T>())
-            .flat_map(|a| a.get_component::<T>())
-            .collect::<Vec<_>>()
-        //Query::<T>::new(self);
-        //todo!()
+    pub fn query_filtered<Q: ReadOnlyQuery, F: QueryFilter>(&self) -> Query<'_, 'static, Q, F> {
+        unsafe { Query::new(self, self.last_change_tick, self.change_tick()) }
     }
 
-    pub fn query_mut<C: Component>(&mut self) -> Vec<&mut C> {
-        self.archetypes
-            .archetypes
-            .iter_mut()
-            .filter(|a| a.has_component::<C>())
-            .flat_map(|a| a.get_component_mut::<C>())
-            .collect::<Vec<_>>()
+    pub fn query_filtered_mut<Q: ComponentsQuery, F: QueryFilter>(
+        &mut self,
+    ) -> Query<'_, 'static, Q, F> {
+        unsafe { Query::new(self, self.last_change_tick, self.change_tick()) }
     }
 
     pub fn query_single<C: Component>(&self) -> &C {
         self.archetypes
             .archetypes
             .iter()
-            .find(|a| a.has_component::<C>())
+            .find(|a| a.has_component::<C>() && !a.is_empty())
             .unwrap()
             .get_single::<C>()
     }
 
-    pub fn query_single_mut<C: Component>(&mut self) -> &mut C {
-        self.archetypes
+    pub fn query_single_mut<C: Component>(&mut self) -> Mut<'_, C> {
+        let this_run = self.change_tick();
+
+        let (value, ticks) = self
+            .archetypes
             .archetypes
             .iter_mut()
-            .find(|a| a.has_component::<C>())
+            .find(|a| a.has_component::<C>() && !a.is_empty())
+            .unwrap()
+            .column_mut::<C>()
             .unwrap()
-            .get_single_mut::<C>()
+            .get_with_ticks_mut(0)
+            .unwrap();
+
+        Mut::new(value, &mut ticks.changed, this_run)
+    }
+}
+
+/// Why a world couldn't be cloned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloneError {
+    /// A component type with values has no way to be cloned.
+    NotClonable(&'static str),
+}
+
+impl fmt::Display for CloneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloneError::NotClonable(name) => {
+                write!(f, "component `{}` can't be cloned", name)
+            }
+        }
     }
 }
+
+impl Error for CloneError {}
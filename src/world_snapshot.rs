@@ -0,0 +1,346 @@
+use std::any::TypeId;
+
+use crate::{entity::EntityMeta, Column, ComponentTicks, Entity, SnapshotError, World, WorldId};
+
+/// A binary copy of every entity and its components, for saving and restoring
+/// a world many times a second, as rollback netcode does.
+///
+/// Every component type with values in the world must be registered with
+/// [`World::register_pod`], so columns can be copied as plain bytes. A
+/// snapshot can only be restored into the world it was taken from, and
+/// capturing into or restoring from an existing snapshot reuses its buffers.
+#[derive(Default)]
+pub struct WorldSnapshot {
+    /// The world the snapshot was captured from, if any.
+    world: Option<WorldId>,
+    entities: Vec<EntityMeta>,
+    freed: Vec<usize>,
+    count: usize,
+    archetypes: Vec<TableSnapshot>,
+    sparse_sets: Vec<TableSnapshot>,
+}
+
+/// The rows of one archetype or sparse set.
+#[derive(Default)]
+struct TableSnapshot {
+    types: Vec<TypeId>,
+    entities: Vec<Entity>,
+    columns: Vec<ColumnSnapshot>,
+}
+
+#[derive(Default)]
+struct ColumnSnapshot {
+    bytes: Vec<u8>,
+    ticks: Vec<ComponentTicks>,
+}
+
+impl WorldSnapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the contents of the snapshot with the current state of
+    /// `world`. Fails if a component that isn't plain data has values, in
+    /// which case the snapshot is left cleared.
+    pub fn capture(&mut self, world: &World) -> Result<(), SnapshotError> {
+        self.entities.clear();
+        self.freed.clear();
+
+        if let Err(error) = self.capture_tables(world) {
+            *self = Self::default();
+            return Err(error);
+        }
+
+        self.world = Some(world.id());
+        self.entities.extend_from_slice(&world.entities.entities);
+        self.freed.extend_from_slice(world.entities.freed());
+        self.count = world.entities.count();
+
+        Ok(())
+    }
+
+    fn capture_tables(&mut self, world: &World) -> Result<(), SnapshotError> {
+        let archetypes = world.archetypes.archetypes.iter();
+        let tables = archetypes.map(|archetype| (archetype.entities(), &archetype.components[..]));
+
+        capture_tables(world, &mut self.archetypes, tables)?;
+
+        let sparse_sets = world
+            .sparse_sets
+            .iter()
+            .map(|set| (set.entities(), std::slice::from_ref(set.column())));
+
+        capture_tables(world, &mut self.sparse_sets, sparse_sets)
+    }
+
+    /// The number of live entities in the snapshot.
+    pub fn len(&self) -> usize {
+        self.archetypes
+            .iter()
+            .map(|table| table.entities.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of bytes of component data in the snapshot.
+    pub fn byte_len(&self) -> usize {
+        let tables = self.archetypes.iter().chain(&self.sparse_sets);
+
+        tables
+            .flat_map(|table| &table.columns)
+            .map(|column| column.bytes.len())
+            .sum()
+    }
+
+    /// Puts the world back in the state it was captured in. Entities spawned
+    /// since are removed, without running hooks, and archetypes created since
+    /// are left empty.
+    pub(crate) fn restore(&self, world: &mut World) {
+        assert!(
+            self.world == Some(world.id()),
+            "the snapshot was taken from a different world"
+        );
+
+        let archetypes = &mut world.archetypes.archetypes;
+
+        // Archetypes are never removed, so the world still has every one the
+        // snapshot does, in the same place.
+        debug_assert!(
+            self.archetypes.len() <= archetypes.len()
+                && self
+                    .archetypes
+                    .iter()
+                    .zip(archetypes.iter())
+                    .all(|(table, archetype)| *table.types == *archetype.types)
+        );
+
+        for (index, archetype) in archetypes.iter_mut().enumerate() {
+            archetype.entities.clear();
+
+            let Some(table) = self.archetypes.get(index) else {
+                archetype.components.iter_mut().for_each(Column::clear);
+                continue;
+            };
+
+            archetype.entities.extend_from_slice(&table.entities);
+
+            for (column, saved) in archetype.components.iter_mut().zip(&table.columns) {
+                // Only plain data columns are saved with rows.
+                unsafe { column.copy_from(&saved.bytes, &saved.ticks) };
+            }
+        }
+
+        for set in world.sparse_sets.iter_mut() {
+            let type_id = set.info().id();
+
+            match self
+                .sparse_sets
+                .iter()
+                .find(|table| table.types == [type_id])
+            {
+                Some(table) => unsafe {
+                    let column = &table.columns[0];
+                    set.copy_from(&table.entities, &column.bytes, &column.ticks);
+                },
+                None => unsafe { set.copy_from(&[], &[], &[]) },
+            }
+        }
+
+        world
+            .entities
+            .copy_from(&self.entities, &self.freed, self.count);
+    }
+}
+
+/// Copies each table into `saved`, reusing the buffers already there.
+fn capture_tables<'w>(
+    world: &World,
+    saved: &mut Vec<TableSnapshot>,
+    tables: impl Iterator<Item = (&'w [Entity], &'w [Column])>,
+) -> Result<(), SnapshotError> {
+    let mut len = 0;
+
+    for (entities, columns) in tables {
+        if saved.len() == len {
+            saved.push(TableSnapshot::default());
+        }
+
+        let table = &mut saved[len];
+        len += 1;
+
+        table.types.clear();
+        table
+            .types
+            .extend(columns.iter().map(|column| column.info().id()));
+        table.entities.clear();
+        table.entities.extend_from_slice(entities);
+        table
+            .columns
+            .resize_with(columns.len(), ColumnSnapshot::default);
+
+        for (column, saved) in columns.iter().zip(&mut table.columns) {
+            saved.bytes.clear();
+            saved.ticks.clear();
+
+            if column.is_empty() {
+                continue;
+            }
+
+            let pod = world
+                .components()
+                .get_by_type_id(column.info().id())
+                .is_some_and(|descriptor| descriptor.is_pod());
+
+            if !pod {
+                return Err(SnapshotError::NotPod(column.info().type_name().to_owned()));
+            }
+
+            saved.bytes.extend_from_slice(column.bytes());
+            saved.ticks.extend(column.ticks());
+        }
+    }
+
+    saved.truncate(len);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StorageType;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Position(f32, f32);
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Velocity(f32, f32);
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Stunned;
+
+    fn new_world() -> World {
+        let mut world = World::new();
+
+        world.register_component::<Stunned>(StorageType::SparseSet);
+        world.register_pod::<Position>();
+        world.register_pod::<Velocity>();
+        world.register_pod::<Stunned>();
+
+        world
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut world = new_world();
+
+        let a = world.spawn((Position(0.0, 0.0), Velocity(1.0, 0.0)));
+        let b = world.spawn((Position(5.0, 5.0),));
+        let c = world.spawn((Position(9.0, 9.0), Stunned));
+        world.remove_entity(b);
+
+        let snapshot = world.snapshot().unwrap();
+        let location = |world: &World, entity| {
+            let location = world.entities.get(entity).unwrap();
+            (location.archetype(), location.row)
+        };
+        let locations = [a, c].map(|entity| location(&world, entity));
+
+        assert_eq!(snapshot.len(), 2);
+
+        world.get_mut::<Position>(a).unwrap().0 = 1.0;
+        world.remove_component::<Stunned>(c);
+        world.add_component(c, Velocity(0.0, 1.0));
+        let d = world.spawn((Position(2.0, 2.0), Stunned));
+        let e = world.spawn((String::from("later"),));
+        world.remove_entity(a);
+
+        world.restore_snapshot(&snapshot);
+
+        assert_eq!([a, c].map(|entity| location(&world, entity)), locations);
+        assert_eq!(world.get::<Position>(a), Some(&Position(0.0, 0.0)));
+        assert_eq!(world.get::<Velocity>(c), None);
+        assert!(world.entity(c).has_component::<Stunned>());
+        assert!(world.get_entity(b).is_none());
+        assert!(world.get_entity(d).is_none());
+        assert!(world.get_entity(e).is_none());
+        assert_eq!(world.query::<&String>().iter().count(), 0);
+
+        // The entities spawned since are handed out again.
+        assert_eq!(world.spawn((Stunned,)), d);
+        assert_eq!(world.spawn((Stunned,)), e);
+    }
+
+    #[test]
+    fn test_capture_reuses_snapshot() {
+        let mut world = new_world();
+        let mut snapshot = WorldSnapshot::new();
+
+        let entities = (0..100)
+            .map(|i| world.spawn((Position(i as f32, 0.0), Velocity(1.0, 1.0))))
+            .collect::<Vec<_>>();
+
+        snapshot.capture(&world).unwrap();
+
+        assert_eq!(snapshot.byte_len(), 100 * 16);
+
+        let step = |world: &mut World| {
+            for mut position in world.query_mut::<&mut Position>() {
+                position.1 += 1.0;
+            }
+        };
+
+        for frame in 1..=3 {
+            step(&mut world);
+            snapshot.capture(&world).unwrap();
+
+            step(&mut world);
+            world.restore_snapshot(&snapshot);
+
+            assert_eq!(
+                world.get::<Position>(entities[5]),
+                Some(&Position(5.0, frame as f32))
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "different world")]
+    fn test_restore_into_fork() {
+        let mut world = new_world();
+        world.spawn((Position(0.0, 0.0),));
+
+        let snapshot = world.snapshot().unwrap();
+        let mut fork = world.clone_world().unwrap();
+
+        fork.restore_snapshot(&snapshot);
+    }
+
+    #[test]
+    fn test_not_pod() {
+        let mut world = new_world();
+        let entity = world.spawn((Position(0.0, 0.0), String::from("a")));
+
+        assert_eq!(
+            world.snapshot().err(),
+            Some(SnapshotError::NotPod(
+                std::any::type_name::<String>().into()
+            ))
+        );
+
+        assert_eq!(
+            world.snapshot().err().unwrap().to_string(),
+            format!(
+                "component `{}` isn't registered as plain data",
+                std::any::type_name::<String>()
+            )
+        );
+
+        world.remove_component::<String>(entity);
+
+        assert!(world.snapshot().is_ok());
+    }
+}